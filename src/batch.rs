@@ -0,0 +1,143 @@
+use crate::error::Error;
+use crate::image::SearchOutcome;
+use std::cmp::Ordering;
+
+/// Results of searching all the templates in one main image of a batch.
+#[derive(Debug, PartialEq)]
+pub struct BatchEntry {
+    pub name: String,
+    // Position of the main image in the batch input, so the caller can find the image again after ranking
+    pub input_index: usize,
+    // One outcome per frame of an animation or page of a TIFF file, still images have a single one
+    pub outcome: Result<Vec<SearchOutcome>, Error>,
+}
+
+fn frame_best_mse(frame: &SearchOutcome) -> Option<f64> {
+    frame
+        .results
+        .iter()
+        .filter_map(|r| r.get_best_mse())
+        .min_by(f64::total_cmp)
+}
+
+impl BatchEntry {
+    /// The lowest MSE over all templates and frames, None when nothing was found or the search failed.
+    pub fn get_best_mse(&self) -> Option<f64> {
+        self.outcome
+            .as_ref()
+            .ok()?
+            .iter()
+            .filter_map(frame_best_mse)
+            .min_by(f64::total_cmp)
+    }
+
+    /// Index of the frame with the lowest MSE, the first one wins a tie.
+    pub fn get_best_frame(&self) -> Option<usize> {
+        self.outcome
+            .as_ref()
+            .ok()?
+            .iter()
+            .enumerate()
+            .filter_map(|(i, frame)| Some((i, frame_best_mse(frame)?)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.outcome.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn get_match_count(&self) -> usize {
+        self.outcome.as_ref().map_or(0, |frames| {
+            frames
+                .iter()
+                .flat_map(|frame| &frame.results)
+                .map(|r| r.get_matches().len())
+                .sum()
+        })
+    }
+}
+
+/**
+ * Orders the entries by the best match, so images containing the template come first.
+ * Images without any match follow and failed searches are last, both in input order.
+ */
+pub fn rank(entries: &mut [BatchEntry]) {
+    entries.sort_by(|a, b| match (a.get_best_mse(), b.get_best_mse()) {
+        (Some(a_mse), Some(b_mse)) => a_mse.total_cmp(&b_mse),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a
+            .outcome
+            .is_err()
+            .cmp(&b.outcome.is_err())
+            .then(a.input_index.cmp(&b.input_index)),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{SearchResult, SearchResults, SearchStatistics};
+
+    fn frame(tse: Option<u64>) -> SearchOutcome {
+        let mut results =
+            SearchResults::new("t".to_string(), 10, 1, 1, 10, 10, 4, 65536.0, 1 << 20);
+        if let Some(tse) = tse {
+            results.push(SearchResult { x: 0, y: 0, tse });
+        }
+        SearchOutcome {
+            results: vec![results],
+            statistics: SearchStatistics::default(),
+            error_landscapes: Vec::new(),
+        }
+    }
+
+    fn entry(input_index: usize, tse: Option<u64>) -> BatchEntry {
+        BatchEntry {
+            name: format!("image{}", input_index),
+            input_index,
+            outcome: Ok(vec![frame(tse)]),
+        }
+    }
+
+    #[test]
+    fn test_rank() {
+        let mut entries = vec![
+            entry(0, None),
+            BatchEntry {
+                name: "broken".to_string(),
+                input_index: 1,
+                outcome: Err(Error::Decode {
+                    image: "broken".to_string(),
+                    reason: "unexpected end of file".to_string(),
+                }),
+            },
+            entry(2, Some(100)),
+            entry(3, None),
+            entry(4, Some(5)),
+        ];
+        rank(&mut entries);
+        let order: Vec<usize> = entries.iter().map(|e| e.input_index).collect();
+        assert_eq!(order, vec![4, 2, 0, 3, 1]);
+    }
+
+    #[test]
+    fn test_frames() {
+        let animation = BatchEntry {
+            name: "animation".to_string(),
+            input_index: 0,
+            outcome: Ok(vec![
+                frame(None),
+                frame(Some(50)),
+                frame(Some(20)),
+                frame(Some(20)),
+            ]),
+        };
+        assert_eq!(animation.get_frame_count(), 4);
+        assert_eq!(animation.get_match_count(), 3);
+        assert_eq!(animation.get_best_frame(), Some(2));
+        assert_eq!(animation.get_best_mse(), entry(1, Some(20)).get_best_mse());
+        assert_eq!(entry(2, None).get_best_frame(), None);
+    }
+}
@@ -0,0 +1,560 @@
+use std::future::Future;
+use std::pin::pin;
+use std::process::ExitCode;
+use std::task::{Context, Poll, Waker};
+use subimage_search::batch::{BatchEntry, rank};
+use subimage_search::config::{
+    Channels, ErrorLandscape, Metric, SearchConfig, ThresholdMode, ThresholdUnit,
+};
+use subimage_search::error::Error;
+use subimage_search::image::{
+    CancellationToken, DecodedImage, DecodedTemplate, Mask, NEAREST_MISS_GRID, NearestMiss, Rect,
+    SearchOutcome, SearchStatistics,
+};
+use subimage_search::presets::{Preset, builtin_presets};
+
+const USAGE: &str = "Usage: subimage-search-cli [OPTIONS] --template <FILE> <MAIN_IMAGE>...
+       subimage-search-cli [OPTIONS] --crop <X,Y,W,H> <MAIN_IMAGE>...
+
+Searches the templates in each main image and prints the main images ranked by the best match.
+
+Options:
+  -t, --template <FILE>     Image to search for, can be repeated
+      --crop <X,Y,W,H>      Search also for this region of the first main image, its other occurrences are reported
+      --config <FILE>       Search configuration saved as JSON, e.g., from the web application
+      --preset <PRESET>     Name of a built-in preset or a preset file exported from the web application
+      --max-mse <PERCENT>   Maximum difference (mean squared error) in percent, auto to pick it from the gap
+                            between the best positions and the rest, or none for the --max-results best
+                            positions whatever their differences [default: 1]
+      --min-psnr <DB>       Minimum peak signal-to-noise ratio of a match instead of --max-mse
+      --max-rmse <LEVELS>   Maximum root mean squared error in 8-bit levels (0-255) instead of --max-mse
+      --max-deviation <LEVELS>
+                            No subpixel of a match may differ by more 8-bit levels, instead of --max-mse
+      --metric <METRIC>     Score of a position, mse or matching-pixels, the fraction of pixels differing by more
+                            than --pixel-tolerance, so --max-mse is the percent of pixels that may differ,
+                            or normalized-channels or normalized-luminance, ignoring brightness and contrast
+                            [default: mse]
+      --pixel-tolerance <PERCENT>
+                            Largest MSE of a single pixel counted as matching by matching-pixels [default: 0.1]
+      --max-results <N>     Maximum number of results per template and main image [default: 10]
+      --channels <CHANNELS> Compared channels, rgba or rgb [default: rgba]
+      --raw-pixels          Ignore EXIF orientation and embedded color profiles of the images
+      --error-landscape <MODE>
+                            Record the error of every position, off, capped or exact [default: off]
+      --heatmap <DIR>       Write the recorded errors as 16-bit PNG and raw f32 files, implies --error-landscape capped
+      --print-config        Print the effective configuration as JSON to stderr
+      --stats               Print search statistics of each main image to stderr
+  -h, --help                Print this help
+
+Options given on the command line override the values from --config or --preset.";
+
+struct Args {
+    templates: Vec<String>,
+    main_images: Vec<String>,
+    config: SearchConfig,
+    print_config: bool,
+    stats: bool,
+    heatmap_dir: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut templates = Vec::new();
+    let mut main_images = Vec::new();
+    let mut config_path = None;
+    let mut preset = None;
+    let mut max_mse = None;
+    let mut threshold_mode = None;
+    let mut threshold_unit = None;
+    let mut max_results = None;
+    let mut metric = None;
+    let mut pixel_tolerance = None;
+    let mut channels = None;
+    let mut raw_pixels = false;
+    let mut crop = None;
+    let mut print_config = false;
+    let mut stats = false;
+    let mut error_landscape = None;
+    let mut heatmap_dir = None;
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().ok_or(format!("missing value for {}", name));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            "-t" | "--template" => templates.push(value(&arg)?),
+            "--config" => config_path = Some(value(&arg)?),
+            "--preset" => preset = Some(value(&arg)?),
+            "--print-config" => print_config = true,
+            "--stats" => stats = true,
+            "--raw-pixels" => raw_pixels = true,
+            "--crop" => crop = Some(parse_rect(&value(&arg)?)?),
+            "--heatmap" => heatmap_dir = Some(value(&arg)?),
+            "--error-landscape" => {
+                error_landscape = Some(match value(&arg)?.as_str() {
+                    "off" => ErrorLandscape::Off,
+                    "capped" => ErrorLandscape::Capped,
+                    "exact" => ErrorLandscape::Exact,
+                    other => return Err(format!("invalid --error-landscape: {}", other)),
+                })
+            }
+            "--max-mse" => match value(&arg)?.as_str() {
+                "auto" => threshold_mode = Some(ThresholdMode::Automatic),
+                "none" => threshold_mode = Some(ThresholdMode::BestK),
+                percent => {
+                    max_mse = Some(
+                        percent
+                            .parse::<f64>()
+                            .map_err(|e| format!("invalid --max-mse: {}", e))?
+                            / 100.0,
+                    );
+                    threshold_mode = Some(ThresholdMode::Fixed);
+                    threshold_unit = Some(ThresholdUnit::Mse);
+                }
+            },
+            "--min-psnr" | "--max-rmse" | "--max-deviation" => {
+                let unit = match arg.as_str() {
+                    "--min-psnr" => ThresholdUnit::Psnr,
+                    "--max-rmse" => ThresholdUnit::Rmse,
+                    _ => ThresholdUnit::MaxDeviation,
+                };
+                let threshold = value(&arg)?
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid {}: {}", arg, e))?;
+                max_mse = Some(unit.to_mse(threshold));
+                threshold_mode = Some(ThresholdMode::Fixed);
+                threshold_unit = Some(unit);
+            }
+            "--metric" => {
+                metric = Some(match value(&arg)?.as_str() {
+                    "mse" => Metric::Mse,
+                    "matching-pixels" => Metric::MatchingPixels,
+                    "normalized-channels" => Metric::NormalizedChannels,
+                    "normalized-luminance" => Metric::NormalizedLuminance,
+                    other => return Err(format!("invalid --metric: {}", other)),
+                })
+            }
+            "--pixel-tolerance" => {
+                pixel_tolerance = Some(
+                    value(&arg)?
+                        .parse::<f64>()
+                        .map_err(|e| format!("invalid --pixel-tolerance: {}", e))?
+                        / 100.0,
+                )
+            }
+            "--max-results" => {
+                max_results = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|e| format!("invalid --max-results: {}", e))?,
+                )
+            }
+            "--channels" => {
+                channels = Some(match value(&arg)?.as_str() {
+                    "rgba" => Channels::Rgba,
+                    "rgb" => Channels::Rgb,
+                    other => return Err(format!("invalid --channels: {}", other)),
+                })
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => main_images.push(arg),
+        }
+    }
+    if main_images.is_empty() {
+        return Err("at least one main image is required".to_string());
+    }
+
+    let mut builder = match (config_path, preset) {
+        (Some(_), Some(_)) => return Err("--config and --preset cannot be combined".to_string()),
+        (Some(path), None) => SearchConfig::from_json(&read_file(&path)?)
+            .map_err(|e| format!("{}: {}", path, e))?
+            .into_builder(),
+        (None, Some(preset)) => load_preset(&preset)?.config.into_builder(),
+        (None, None) => SearchConfig::builder(),
+    };
+    if let Some(max_mse) = max_mse {
+        builder = builder.max_mse(max_mse);
+    }
+    if let Some(threshold_mode) = threshold_mode {
+        builder = builder.threshold_mode(threshold_mode);
+    }
+    if let Some(threshold_unit) = threshold_unit {
+        builder = builder.threshold_unit(threshold_unit);
+    }
+    if let Some(metric) = metric {
+        builder = builder.metric(metric);
+    }
+    if let Some(pixel_tolerance) = pixel_tolerance {
+        builder = builder.pixel_tolerance(pixel_tolerance);
+    }
+    if let Some(max_results) = max_results {
+        builder = builder.max_results(max_results);
+    }
+    if let Some(channels) = channels {
+        builder = builder.channels(channels);
+    }
+    if raw_pixels {
+        builder = builder.apply_image_metadata(false);
+    }
+    if crop.is_some() {
+        builder = builder.template_region(crop);
+    }
+    if let Some(error_landscape) = error_landscape {
+        builder = builder.error_landscape(error_landscape);
+    }
+    let mut config = builder.build().map_err(|e| e.to_string())?;
+    if heatmap_dir.is_some() && config.error_landscape == ErrorLandscape::Off {
+        config.error_landscape = ErrorLandscape::Capped;
+    }
+    // The template region can also come from --config or --preset
+    if templates.is_empty() && config.template_region.is_none() {
+        return Err("at least one --template or --crop is required".to_string());
+    }
+    Ok(Args {
+        templates,
+        main_images,
+        config,
+        print_config,
+        stats,
+        heatmap_dir,
+    })
+}
+
+fn parse_rect(value: &str) -> Result<Rect, String> {
+    let invalid = || format!("invalid --crop {}, expected X,Y,W,H", value);
+    let numbers = value
+        .split(',')
+        .map(|n| n.trim().parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match numbers[..] {
+        [x, y, width, height] => Ok(Rect {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("error reading {}: {}", path, e))
+}
+
+fn load_preset(name_or_path: &str) -> Result<Preset, String> {
+    if let Some(preset) = builtin_presets()
+        .into_iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name_or_path))
+    {
+        return Ok(preset);
+    }
+    Preset::from_json(&read_file(name_or_path)?).map_err(|e| format!("{}: {}", name_or_path, e))
+}
+
+fn print_usage() {
+    println!("{}\n\nBuilt-in presets:", USAGE);
+    for preset in builtin_presets() {
+        println!("  {}", preset.name);
+    }
+}
+
+fn read_image_file(path: &str) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| Error::Decode {
+        image: path.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+// The same decoder as in the web application, so both find the same matches
+fn load_image(path: &str, config: &SearchConfig) -> Result<DecodedImage, Error> {
+    DecodedImage::decode(path, &read_image_file(path)?, config.apply_image_metadata)
+}
+
+fn load_frames(path: &str, config: &SearchConfig) -> Result<Vec<DecodedImage>, Error> {
+    DecodedImage::decode_frames(path, &read_image_file(path)?, config.apply_image_metadata)
+}
+
+// The search yields only in the browser, so the future completes without ever being woken up
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+// Scores are printed in the unit of the threshold
+fn print_entry(rank: usize, entry: &BatchEntry, unit: ThresholdUnit) {
+    match &entry.outcome {
+        Ok(outcome) => {
+            let best = entry
+                .get_best_mse()
+                .map_or("-".to_string(), |mse| unit.mse_unit().format(mse));
+            println!(
+                "{:>4}  {:>12}  {:>7}  {}",
+                rank,
+                best,
+                entry.get_match_count(),
+                entry.name
+            );
+            for (frame_index, frame) in outcome.iter().enumerate() {
+                // Frames are numbered only in animations and multi-page images
+                let frame_label = if outcome.len() > 1 {
+                    format!(" in frame {}", frame_index + 1)
+                } else {
+                    String::new()
+                };
+                for search_results in &frame.results {
+                    for m in search_results.get_matches() {
+                        println!(
+                            "{:>4}  {:>12}  {:>7}    {} at ({}, {}){}",
+                            "",
+                            search_results.format_score(m, unit),
+                            "",
+                            search_results.get_template_name(),
+                            m.x,
+                            m.y,
+                            frame_label
+                        );
+                    }
+                    if let Some(threshold) = search_results.get_auto_threshold() {
+                        println!(
+                            "{:>4}  {:>12}  {:>7}    {} picked a maximum difference of {}{}",
+                            "",
+                            "",
+                            "",
+                            search_results.get_template_name(),
+                            unit.mse_unit().format(threshold),
+                            frame_label
+                        );
+                    }
+                    if let Some(miss) = search_results.get_nearest_miss() {
+                        print_nearest_miss(
+                            search_results.get_template_name(),
+                            miss,
+                            &frame_label,
+                            unit,
+                        );
+                    }
+                }
+            }
+        }
+        Err(err) => println!(
+            "{:>4}  {:>12}  {:>7}  {}: {}",
+            rank, "error", "", entry.name, err
+        ),
+    }
+}
+
+// Best position of a template without matches, with the MSE of each part of the template
+fn print_nearest_miss(
+    template_name: &str,
+    miss: &NearestMiss,
+    frame_label: &str,
+    unit: ThresholdUnit,
+) {
+    println!(
+        "{:>4}  {:>12}  {:>7}    {} nearest miss at ({}, {}){}; set the maximum difference to at least its score to find it",
+        "",
+        miss.format_score(unit),
+        "",
+        template_name,
+        miss.x,
+        miss.y,
+        frame_label
+    );
+    // The parts are known only as an MSE, not as a deviation
+    let region_unit = unit.mse_unit();
+    for row in miss.region_mse.chunks(NEAREST_MISS_GRID as usize) {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|&mse| {
+                if mse.is_nan() {
+                    format!("{:>12}", "-")
+                } else {
+                    format!("{:>12}", region_unit.format(mse))
+                }
+            })
+            .collect();
+        println!("{:>4}  {:>12}  {:>7}      {}", "", "", "", cells.join(" "));
+    }
+    if let Some(cause) = miss.likely_cause() {
+        println!(
+            "{:>4}  {:>12}  {:>7}      likely cause: {}",
+            "", "", "", cause
+        );
+    }
+}
+
+// One pair of files per frame and template, numbered as they are given
+fn write_heatmaps(dir: &str, main_image: &str, frames: &[SearchOutcome]) -> Result<(), String> {
+    let stem = std::path::Path::new(main_image)
+        .file_stem()
+        .map_or("main".into(), |stem| stem.to_string_lossy());
+    for (frame_index, frame) in frames.iter().enumerate() {
+        let frame_label = if frames.len() > 1 {
+            format!("-frame{}", frame_index + 1)
+        } else {
+            String::new()
+        };
+        for (template_index, landscape) in frame.error_landscapes.iter().enumerate() {
+            let base = format!("{}/{}{}-t{}", dir, stem, frame_label, template_index + 1);
+            let png = landscape.to_gray16_png().map_err(|e| e.to_string())?;
+            let raw_path = format!(
+                "{}.errors.{}x{}.f32",
+                base,
+                landscape.get_width(),
+                landscape.get_height()
+            );
+            for (path, bytes) in [
+                (format!("{}.errors.png", base), png),
+                (raw_path, landscape.to_raw_f32()),
+            ] {
+                std::fs::write(&path, bytes)
+                    .map_err(|e| format!("error writing {}: {}", path, e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_statistics(statistics: &SearchStatistics) {
+    eprintln!(
+        "      positions evaluated: {}, rejected early: {}, average rows before rejection: {}",
+        statistics.positions_evaluated,
+        statistics.positions_rejected_early,
+        statistics
+            .get_average_rows_before_rejection()
+            .map_or("-".to_string(), |rows| format!("{:.2}", rows))
+    );
+    eprintln!(
+        "      threshold tightenings: {}",
+        statistics.threshold_tightenings.len()
+    );
+    for phase in &statistics.phases {
+        eprintln!("      {}: {:.0} ms", phase.name, phase.duration_ms);
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut template_images = Vec::with_capacity(args.templates.len());
+    for path in &args.templates {
+        match load_image(path, &args.config) {
+            Ok(image) => template_images.push(image),
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let mut template_names = args.templates.clone();
+    // Like in the web application, the region is taken from the first main image
+    if let Some(region) = &args.config.template_region {
+        match load_image(&args.main_images[0], &args.config).and_then(|main| main.crop(region)) {
+            Ok(image) => {
+                template_images.push(image);
+                template_names.push(format!("{} {}", args.main_images[0], region));
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let templates: Vec<DecodedTemplate> = template_names
+        .into_iter()
+        .zip(template_images.iter())
+        .map(|(name, image)| DecodedTemplate {
+            name,
+            image,
+            max_mse: None,
+            max_results: None,
+        })
+        .collect();
+    if args.print_config {
+        eprintln!("{}", args.config.to_json());
+    }
+
+    let count = args.main_images.len();
+    let mut entries: Vec<BatchEntry> = args
+        .main_images
+        .iter()
+        .enumerate()
+        .map(|(input_index, path)| {
+            eprintln!("[{}/{}] {}", input_index + 1, count, path);
+            let outcome = load_frames(path, &args.config).and_then(|frames| {
+                // Excluded regions are the only part of the mask available in the CLI.
+                // All frames have the same size, only the excluded template region differs.
+                frames
+                    .iter()
+                    .enumerate()
+                    .map(|(frame_index, frame)| {
+                        let excluded_regions =
+                            args.config.excluded_regions_in(input_index, frame_index);
+                        let mask = if excluded_regions.is_empty() {
+                            None
+                        } else {
+                            Some(Mask::from_parts(
+                                frame.width(),
+                                frame.height(),
+                                None,
+                                &excluded_regions,
+                            )?)
+                        };
+                        block_on(frame.find_subimage(
+                            &templates,
+                            &args.config,
+                            |_| {},
+                            |_, _| {},
+                            mask.as_ref(),
+                            &CancellationToken::default(),
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            });
+            if let (true, Ok(frames)) = (args.stats, &outcome) {
+                for frame in frames {
+                    print_statistics(&frame.statistics);
+                }
+            }
+            if let (Some(dir), Ok(frames)) = (&args.heatmap_dir, &outcome)
+                && let Err(err) = write_heatmaps(dir, path, frames)
+            {
+                eprintln!("{}", err);
+            }
+            BatchEntry {
+                name: path.clone(),
+                input_index,
+                outcome,
+            }
+        })
+        .collect();
+    rank(&mut entries);
+
+    let unit = args.config.threshold_unit;
+    // The scores carry their units, the names of some are too long for the column
+    println!(
+        "{:>4}  {:>12}  {:>7}  Image",
+        "Rank", "Best score", "Matches"
+    );
+    for (i, entry) in entries.iter().enumerate() {
+        print_entry(i + 1, entry, unit);
+    }
+    // The other main images are still reported, but scripts have to notice the failure
+    if entries.iter().any(|entry| entry.outcome.is_err()) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,98 @@
+use crate::batch::BatchEntry;
+use crate::config::{Metric, ThresholdUnit};
+use std::rc::Rc;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct BatchResultsViewProps {
+    // Ranked entries, the best match first
+    pub entries: Rc<Vec<BatchEntry>>,
+    pub selected: Option<usize>,
+    pub on_select: Callback<usize>,
+    #[prop_or_default]
+    pub unit: ThresholdUnit,
+    #[prop_or_default]
+    pub metric: Metric,
+}
+
+#[function_component(BatchResultsView)]
+pub fn batch_results_view(props: &BatchResultsViewProps) -> Html {
+    let found = props
+        .entries
+        .iter()
+        .filter(|e| e.get_match_count() > 0)
+        .count();
+    // The column is useful only when some main image is an animation or has more pages
+    let has_frames = props.entries.iter().any(|e| e.get_frame_count() > 1);
+    // Only the MSE of the best match is known, not its deviation
+    let unit = props.unit.mse_unit();
+    html! {
+        <div class="result-container">
+            <h2>{"Batch results"}</h2>
+            <div class="result-message">
+                <h3>{format!("Matches found in {} of {} images", found, props.entries.len())}</h3>
+            </div>
+            <table class="batch-table">
+                <thead>
+                    <tr>
+                        <th>{"Rank"}</th>
+                        <th>{"Image"}</th>
+                        <th>{format!("Best {}", props.metric.score_label(unit))}</th>
+                        {
+                            if has_frames {
+                                html! { <th>{"Best frame"}</th> }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        <th>{"Matches"}</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {
+                        props.entries.iter().enumerate().map(|(i, entry)| {
+                            let on_click = props.on_select.reform(move |_: MouseEvent| i);
+                            html! {
+                                <tr
+                                    class={classes!(
+                                        "batch-row",
+                                        (props.selected == Some(i)).then_some("selected"),
+                                        entry.outcome.is_err().then_some("failed"),
+                                    )}
+                                    onclick={on_click}
+                                >
+                                    <td>{i + 1}</td>
+                                    <td>{&entry.name}</td>
+                                    <td>{
+                                        match (&entry.outcome, entry.get_best_mse()) {
+                                            (Err(_), _) => "error".to_string(),
+                                            (Ok(_), Some(mse)) => unit.format(mse),
+                                            (Ok(_), None) => "-".to_string(),
+                                        }
+                                    }</td>
+                                    {
+                                        if has_frames {
+                                            html! {
+                                                <td>{
+                                                    match entry.get_best_frame() {
+                                                        Some(frame) if entry.get_frame_count() > 1 => {
+                                                            format!("{} of {}", frame + 1, entry.get_frame_count())
+                                                        }
+                                                        _ => "-".to_string(),
+                                                    }
+                                                }</td>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
+                                    <td>{entry.get_match_count()}</td>
+                                </tr>
+                            }
+                        }).collect::<Html>()
+                    }
+                </tbody>
+            </table>
+        </div>
+    }
+}
@@ -0,0 +1,83 @@
+use crate::image::SearchStatistics;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct DiagnosticsProps {
+    pub statistics: SearchStatistics,
+    // Names of the templates, indexed like in the statistics
+    pub template_names: Vec<String>,
+}
+
+#[function_component(Diagnostics)]
+pub fn diagnostics(props: &DiagnosticsProps) -> Html {
+    let statistics = &props.statistics;
+    let rejected_percent = if statistics.positions_evaluated == 0 {
+        0.0
+    } else {
+        statistics.positions_rejected_early as f64 / statistics.positions_evaluated as f64 * 100.0
+    };
+    let total_ms: f64 = statistics.phases.iter().map(|p| p.duration_ms).sum();
+    html! {
+        <details class="diagnostics">
+            <summary>{"Diagnostics"}</summary>
+            <h4>{"Positions"}</h4>
+            <table>
+                <tr>
+                    <th>{"Evaluated"}</th>
+                    <td>{statistics.positions_evaluated}</td>
+                </tr>
+                <tr>
+                    <th>{"Rejected early"}</th>
+                    <td>{format!("{} ({:.1}%)", statistics.positions_rejected_early, rejected_percent)}</td>
+                </tr>
+                <tr>
+                    <th>{"Average template rows read before rejection"}</th>
+                    <td>{
+                        statistics
+                            .get_average_rows_before_rejection()
+                            .map_or("-".to_string(), |rows| format!("{:.2}", rows))
+                    }</td>
+                </tr>
+            </table>
+            <h4>{"Wall time"}</h4>
+            <table>
+                {
+                    statistics.phases.iter().map(|phase| html! {
+                        <tr>
+                            <th>{&phase.name}</th>
+                            <td>{format!("{:.0} ms", phase.duration_ms)}</td>
+                        </tr>
+                    }).collect::<Html>()
+                }
+                <tr class="total">
+                    <th>{"Total"}</th>
+                    <td>{format!("{:.0} ms", total_ms)}</td>
+                </tr>
+            </table>
+            <h4>{format!("Threshold tightening ({})", statistics.threshold_tightenings.len())}</h4>
+            {
+                if statistics.threshold_tightenings.is_empty() {
+                    html! {
+                        <p>{"The threshold was never tightened, the number of matches didn't reach the maximum number of results."}</p>
+                    }
+                } else {
+                    html! {
+                        <ol>
+                            {
+                                statistics.threshold_tightenings.iter().map(|t| html! {
+                                    <li>{format!(
+                                        "{}: at ({}, {}), new MSE threshold {:.4}%",
+                                        props.template_names.get(t.template_index).map_or("?", |n| n.as_str()),
+                                        t.x,
+                                        t.y,
+                                        t.mse * 100.0
+                                    )}</li>
+                                }).collect::<Html>()
+                            }
+                        </ol>
+                    }
+                }
+            }
+        </details>
+    }
+}
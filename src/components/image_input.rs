@@ -1,4 +1,5 @@
-use wasm_bindgen::JsCast;
+use crate::UploadedImage;
+use ::image::metadata::Orientation;
 use web_sys::{DragEvent, Event, HtmlElement};
 use web_sys::{FileList, HtmlInputElement};
 use yew::prelude::*;
@@ -14,16 +15,28 @@ pub struct ImageInputProps {
     pub input_id: String,
     pub preview_id: String,
     pub on_upload: Callback<FileList>,
-    pub image: Option<String>,
+    pub images: Vec<UploadedImage>,
     pub help: Option<Html>,
     pub disabled: bool,
+    // Allows selecting more files at once; previews get ids with index suffix
+    #[prop_or_default]
+    pub multiple: bool,
+    // Whether the detected orientation and color profile are applied when searching
+    pub apply_metadata: bool,
+    // Images pasted from the clipboard go to this input
+    #[prop_or_default]
+    pub is_paste_target: bool,
+    // Makes this input the paste target, None when it cannot receive pasted images
+    #[prop_or_default]
+    pub on_paste_target: Option<Callback<()>>,
 }
 
 fn on_change(cb: Callback<FileList>) -> Callback<Event> {
     Callback::from(move |e: Event| {
-        let target = e.target().unwrap();
-        let input: HtmlInputElement = target.dyn_into().unwrap();
-        if let Some(files) = input.files() {
+        if let Some(files) = e
+            .target_dyn_into::<HtmlInputElement>()
+            .and_then(|input| input.files())
+        {
             cb.emit(files)
         }
         prevent_default(&e);
@@ -69,9 +82,59 @@ fn on_drag_start(props: &ImageInputProps, label_ref: &NodeRef) -> Callback<DragE
 
 fn node_ref_toggle_class(node_ref: &NodeRef, class: &str, add: bool) {
     if let Some(element) = node_ref.cast::<HtmlElement>() {
-        element.class_list().toggle_with_force(class, add).unwrap();
+        if let Err(err) = element.class_list().toggle_with_force(class, add) {
+            log::warn!("Failed to toggle class {}: {:?}", class, err);
+        }
     } else {
-        panic!("Failed to cast element to HtmlElement: {:?}", node_ref);
+        // Only a visual hint is lost
+        log::warn!("Failed to cast element to HtmlElement: {:?}", node_ref);
+    }
+}
+
+// Only metadata that changes the pixels is displayed, so ordinary images have no caption
+fn metadata_caption(image: &UploadedImage, apply_metadata: bool) -> Html {
+    let Some(metadata) = image.metadata() else {
+        return html! {};
+    };
+    let mut details = Vec::new();
+    if metadata.orientation != Orientation::NoTransforms {
+        details.push(format!(
+            "Orientation: {}",
+            metadata.orientation_description()
+        ));
+    }
+    if let Some(profile) = &metadata.color_profile {
+        details.push(format!("Color profile: {}", profile));
+    }
+    if details.is_empty() {
+        return html! {};
+    }
+    html! {
+        <figcaption class={if apply_metadata {"image-metadata"} else {"image-metadata ignored"}}>
+            { details.join(", ") }
+            { if apply_metadata { "" } else { " (ignored)" } }
+        </figcaption>
+    }
+}
+
+fn paste_hint(props: &ImageInputProps) -> Html {
+    match &props.on_paste_target {
+        _ if props.disabled => html! {},
+        Some(_) if props.is_paste_target => html! {
+            <div class="paste-hint">{"Ctrl+V pastes an image from the clipboard here"}</div>
+        },
+        Some(on_paste_target) => {
+            let on_paste_target = on_paste_target.clone();
+            let onclick = Callback::from(move |e: MouseEvent| {
+                // Clicking the label would open the file dialog
+                e.prevent_default();
+                on_paste_target.emit(());
+            });
+            html! {
+                <button type="button" class="paste-hint" {onclick}>{"Paste here instead"}</button>
+            }
+        }
+        None => html! {},
     }
 }
 
@@ -81,7 +144,11 @@ pub fn image_input(props: &ImageInputProps) -> Html {
     html! {
         <label
             ref={&label_ref}
-            class={if props.disabled {"image-input disabled"} else {"image-input"}}
+            class={classes!(
+                "image-input",
+                props.disabled.then_some("disabled"),
+                (props.is_paste_target && !props.disabled).then_some("paste-target"),
+            )}
             id={format!("{}-container", props.input_id)}
             ondrop={on_drop(props, &label_ref)}
             ondragenter={on_drag_start(props, &label_ref)}
@@ -89,30 +156,36 @@ pub fn image_input(props: &ImageInputProps) -> Html {
             ondragleave={on_drag_leave(&label_ref)}
         >
             <h3>{&props.label}</h3>
+            {paste_hint(props)}
             <input
                 type="file"
                 id={props.input_id.clone()}
                 accept="image/*"
+                multiple={props.multiple}
                 onchange={on_change(props.on_upload.clone())}
                 disabled={props.disabled}
             />
             {
-                if props.image.is_none() {
+                if props.images.is_empty() {
                     html! {
                         <div class="upload-prompt">
                             <div class="upload-icon">{"+"}</div>
-                            <p>{"Click here or drag&drop image"}</p>
+                            <p>{if props.multiple {"Click here or drag&drop images"} else {"Click here or drag&drop image"}}</p>
                         </div>
                     }
                 } else {
-                    html! {
-                        <img
-                            id={props.preview_id.clone()}
-                            class="preview"
-                            alt={format!("{} preview", props.label)}
-                            src={props.image.clone().unwrap_or_default()}
-                        />
-                    }
+                    props.images.iter().enumerate().map(|(i, image)| html! {
+                        <figure class="preview-item">
+                            <img
+                                id={if props.multiple {format!("{}-{}", props.preview_id, i)} else {props.preview_id.clone()}}
+                                class="preview"
+                                alt={format!("{} preview", props.label)}
+                                title={image.name.clone()}
+                                src={image.url.clone()}
+                            />
+                            {metadata_caption(image, props.apply_metadata)}
+                        </figure>
+                    }).collect::<Html>()
                 }
             }
             {props.help.clone().unwrap_or_default()}
@@ -0,0 +1,128 @@
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// Images and errors of a single match. The URLs are object URLs of PNG images owned by the application.
+#[derive(Clone, PartialEq)]
+pub struct Inspection {
+    pub template_name: String,
+    pub x: u32,
+    pub y: u32,
+    pub mse: f64,
+    // Error of the worst pixel, displayed as white in the heatmap
+    pub max_error: f64,
+    pub template_url: String,
+    pub region_url: String,
+    pub heatmap_url: String,
+}
+
+impl Inspection {
+    pub fn urls(&self) -> Vec<String> {
+        vec![
+            self.template_url.clone(),
+            self.region_url.clone(),
+            self.heatmap_url.clone(),
+        ]
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct MatchInspectorProps {
+    pub inspection: Inspection,
+    pub on_close: Callback<()>,
+}
+
+/// Template, matched region and their per-pixel difference side by side, with a blink comparator.
+#[function_component(MatchInspector)]
+pub fn match_inspector(props: &MatchInspectorProps) -> Html {
+    // Opacity of the template over the matched region, in percent
+    let opacity = use_state(|| 50u32);
+    let blink = use_state(|| false);
+    let inspection = &props.inspection;
+
+    let on_opacity_input = {
+        let opacity = opacity.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(value) = e
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.value().parse().ok())
+            {
+                opacity.set(value);
+            }
+        })
+    };
+
+    let on_blink_change = {
+        let blink = blink.clone();
+        Callback::from(move |e: Event| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                blink.set(input.checked());
+            }
+        })
+    };
+
+    html! {
+        <div class="match-inspector">
+            <h3>
+                {format!(
+                    "{} at ({}, {}) - MSE: {:.4}%, worst pixel: {:.4}%",
+                    inspection.template_name,
+                    inspection.x,
+                    inspection.y,
+                    inspection.mse * 100.0,
+                    inspection.max_error * 100.0,
+                )}
+                <button class="region-remove" onclick={props.on_close.reform(|_: MouseEvent| ())}>{"×"}</button>
+            </h3>
+            <div class="inspector-images">
+                <figure>
+                    <img src={inspection.template_url.clone()} alt="Template" />
+                    <figcaption>{"Template"}</figcaption>
+                </figure>
+                <figure>
+                    <img src={inspection.region_url.clone()} alt="Matched region" />
+                    <figcaption>{"Matched region"}</figcaption>
+                </figure>
+                <figure>
+                    <img src={inspection.heatmap_url.clone()} alt="Squared error of each pixel" />
+                    <figcaption>
+                        {"Squared error: black is none, white is the worst pixel"}
+                    </figcaption>
+                </figure>
+                <figure>
+                    <div class="inspector-comparison">
+                        <img src={inspection.region_url.clone()} alt="Matched region" />
+                        <img
+                            class={classes!("inspector-overlay", (*blink).then_some("blink"))}
+                            src={inspection.template_url.clone()}
+                            alt="Template over the matched region"
+                            style={(!*blink).then(|| format!("opacity: {}", *opacity as f64 / 100.0))}
+                        />
+                    </div>
+                    <figcaption>
+                        <label>
+                            {"Template opacity "}
+                            <input
+                                type="range"
+                                id="inspectorOpacityInput"
+                                min="0"
+                                max="100"
+                                value={opacity.to_string()}
+                                oninput={on_opacity_input}
+                                disabled={*blink}
+                            />
+                        </label>
+                        <label>
+                            <input
+                                type="checkbox"
+                                id="inspectorBlinkInput"
+                                checked={*blink}
+                                onchange={on_blink_change}
+                            />
+                            {"Blink"}
+                        </label>
+                    </figcaption>
+                </figure>
+            </div>
+        </div>
+    }
+}
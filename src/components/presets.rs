@@ -0,0 +1,241 @@
+use crate::config::SearchConfig;
+use crate::download_file;
+use crate::error::Error;
+use crate::presets::{Preset, builtin_presets};
+use wasm_bindgen_futures::{JsFuture, spawn_local};
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct PresetPickerProps {
+    pub config: SearchConfig,
+    // Presets saved or imported by the user, the built-in ones are not included
+    pub user_presets: Vec<Preset>,
+    pub disabled: bool,
+    pub on_config_change: Callback<SearchConfig>,
+    pub on_user_presets_change: Callback<Vec<Preset>>,
+}
+
+// Adds the preset, or replaces the user preset of the same name
+fn with_preset(presets: &[Preset], preset: Preset) -> Vec<Preset> {
+    let mut presets: Vec<Preset> = presets
+        .iter()
+        .filter(|p| p.name != preset.name)
+        .cloned()
+        .collect();
+    presets.push(preset);
+    presets
+}
+
+async fn read_preset(file: web_sys::File) -> Result<Preset, Error> {
+    let text = JsFuture::from(file.text())
+        .await
+        .ok()
+        .and_then(|text| text.as_string())
+        .ok_or_else(|| Error::Decode {
+            image: file.name(),
+            reason: "the file cannot be read as text".to_string(),
+        })?;
+    let preset = Preset::from_json(&text)?;
+    preset.validate_user_preset()?;
+    Ok(preset)
+}
+
+/// Applies, saves, exports and imports named search settings.
+#[function_component(PresetPicker)]
+pub fn preset_picker(props: &PresetPickerProps) -> Html {
+    let new_name = use_state(String::new);
+    let error = use_state(|| None::<Error>);
+
+    let builtin = builtin_presets();
+    // The settings need not come from a preset, e.g., when a value was changed afterwards
+    let current = builtin
+        .iter()
+        .chain(props.user_presets.iter())
+        .find(|p| p.config.clone().with_regions_of(&props.config) == props.config)
+        .cloned();
+    let current_is_user_preset = current
+        .as_ref()
+        .is_some_and(|c| props.user_presets.contains(c));
+
+    let on_select = {
+        let presets: Vec<Preset> = builtin
+            .iter()
+            .chain(props.user_presets.iter())
+            .cloned()
+            .collect();
+        let config = props.config.clone();
+        let on_config_change = props.on_config_change.clone();
+        Callback::from(move |e: Event| {
+            let Some(select) = e.target_dyn_into::<HtmlSelectElement>() else {
+                return;
+            };
+            // Saving and importing reject the names of built-in presets, so a name selects one preset
+            if let Some(preset) = presets.iter().find(|p| p.name == select.value()) {
+                // The regions belong to the current images, not to the preset
+                on_config_change.emit(preset.config.clone().with_regions_of(&config));
+            }
+        })
+    };
+
+    let on_name_input = {
+        let new_name = new_name.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(input) = e.target_dyn_into::<HtmlInputElement>() {
+                new_name.set(input.value());
+            }
+        })
+    };
+
+    let on_save = {
+        let new_name = new_name.clone();
+        let error = error.clone();
+        let config = props.config.clone();
+        let user_presets = props.user_presets.clone();
+        let on_user_presets_change = props.on_user_presets_change.clone();
+        Callback::from(move |_: MouseEvent| {
+            let preset = Preset {
+                name: new_name.trim().to_string(),
+                config: config.clone().with_regions_of(&SearchConfig::default()),
+            };
+            match preset.validate_user_preset() {
+                Ok(()) => {
+                    error.set(None);
+                    new_name.set(String::new());
+                    on_user_presets_change.emit(with_preset(&user_presets, preset));
+                }
+                Err(err) => error.set(Some(err)),
+            }
+        })
+    };
+
+    let on_delete = {
+        let current = current.clone();
+        let user_presets = props.user_presets.clone();
+        let on_user_presets_change = props.on_user_presets_change.clone();
+        Callback::from(move |_: MouseEvent| {
+            if let Some(current) = &current {
+                on_user_presets_change.emit(
+                    user_presets
+                        .iter()
+                        .filter(|p| p.name != current.name)
+                        .cloned()
+                        .collect(),
+                );
+            }
+        })
+    };
+
+    let on_export = {
+        let error = error.clone();
+        let config = props.config.clone();
+        let current = current.clone();
+        Callback::from(move |_: MouseEvent| {
+            let preset = current.clone().unwrap_or_else(|| Preset {
+                name: "Custom settings".to_string(),
+                config: config.clone().with_regions_of(&SearchConfig::default()),
+            });
+            if let Err(err) = download_file(
+                &preset.file_name(),
+                preset.to_json().as_bytes(),
+                "application/json",
+            ) {
+                log::error!("{}", err);
+                error.set(Some(err));
+            }
+        })
+    };
+
+    let on_import = {
+        let error = error.clone();
+        let config = props.config.clone();
+        let user_presets = props.user_presets.clone();
+        let on_config_change = props.on_config_change.clone();
+        let on_user_presets_change = props.on_user_presets_change.clone();
+        Callback::from(move |e: Event| {
+            let Some(file) = e
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.files())
+                .and_then(|files| files.get(0))
+            else {
+                return;
+            };
+            let error = error.clone();
+            let config = config.clone();
+            let user_presets = user_presets.clone();
+            let on_config_change = on_config_change.clone();
+            let on_user_presets_change = on_user_presets_change.clone();
+            spawn_local(async move {
+                match read_preset(file).await {
+                    Ok(preset) => {
+                        error.set(None);
+                        on_config_change.emit(preset.config.clone().with_regions_of(&config));
+                        on_user_presets_change.emit(with_preset(&user_presets, preset));
+                    }
+                    Err(err) => error.set(Some(err)),
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="presets">
+            <label>
+                {"Preset: "}
+                <select id="presetInput" onchange={on_select} disabled={props.disabled}>
+                    <option value="" selected={current.is_none()} disabled={true}>{"Custom settings"}</option>
+                    <optgroup label="Built-in">
+                        {
+                            builtin.iter().map(|p| html! {
+                                <option value={p.name.clone()} selected={current.as_ref() == Some(p)}>{&p.name}</option>
+                            }).collect::<Html>()
+                        }
+                    </optgroup>
+                    {
+                        if props.user_presets.is_empty() {
+                            html! {}
+                        } else {
+                            html! {
+                                <optgroup label="Saved">
+                                    {
+                                        props.user_presets.iter().map(|p| html! {
+                                            <option value={p.name.clone()} selected={current.as_ref() == Some(p)}>{&p.name}</option>
+                                        }).collect::<Html>()
+                                    }
+                                </optgroup>
+                            }
+                        }
+                    }
+                </select>
+            </label>
+            <button class="edit-button" onclick={on_delete} disabled={props.disabled || !current_is_user_preset}>
+                {"Delete"}
+            </button>
+            <button class="edit-button" onclick={on_export} disabled={props.disabled}>
+                {"Export"}
+            </button>
+            <label class="edit-button import-button">
+                {"Import"}
+                <input type="file" accept=".json,application/json" onchange={on_import} disabled={props.disabled} />
+            </label>
+            <input
+                type="text"
+                id="presetNameInput"
+                placeholder="Name of the current settings"
+                value={(*new_name).clone()}
+                oninput={on_name_input}
+                disabled={props.disabled}
+            />
+            <button class="edit-button" onclick={on_save} disabled={props.disabled}>
+                {"Save as preset"}
+            </button>
+            {
+                if let Some(err) = &*error {
+                    html! { <div class="error-message">{err.to_string()}</div> }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
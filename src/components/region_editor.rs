@@ -0,0 +1,248 @@
+use crate::image::Rect;
+use web_sys::HtmlImageElement;
+use yew::prelude::*;
+
+/// Meaning of the regions, they are displayed differently.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RegionKind {
+    #[default]
+    Search,
+    Exclusion,
+    // A single region, drawing another one replaces it
+    Template,
+}
+
+impl RegionKind {
+    fn class(self) -> &'static str {
+        match self {
+            RegionKind::Search => "search",
+            RegionKind::Exclusion => "exclusion",
+            RegionKind::Template => "template",
+        }
+    }
+
+    fn empty_text(self) -> &'static str {
+        match self {
+            RegionKind::Search => "Whole image (drag on the image to restrict the search)",
+            RegionKind::Exclusion => "Nothing drawn (drag on the image to exclude an area)",
+            RegionKind::Template => {
+                "Nothing drawn (drag on the image to search for that part of it)"
+            }
+        }
+    }
+
+    fn clear_text(self) -> &'static str {
+        match self {
+            RegionKind::Search => "Clear regions",
+            RegionKind::Exclusion => "Clear excluded areas",
+            RegionKind::Template => "Clear template",
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct RegionEditorProps {
+    pub image: String,
+    pub regions: Vec<Rect>,
+    pub on_change: Callback<Vec<Rect>>,
+    pub disabled: bool,
+    #[prop_or_default]
+    pub kind: RegionKind,
+}
+
+// Converts mouse position to pixel coordinates of the (unscaled) image
+fn to_image_coords(image_ref: &NodeRef, event: &MouseEvent) -> Option<(u32, u32)> {
+    let image = image_ref.cast::<HtmlImageElement>()?;
+    let bounds = image.get_bounding_client_rect();
+    if bounds.width() <= 0.0 || bounds.height() <= 0.0 {
+        return None;
+    }
+    let fx = ((event.client_x() as f64 - bounds.left()) / bounds.width()).clamp(0.0, 1.0);
+    let fy = ((event.client_y() as f64 - bounds.top()) / bounds.height()).clamp(0.0, 1.0);
+    Some((
+        (fx * image.natural_width() as f64).round() as u32,
+        (fy * image.natural_height() as f64).round() as u32,
+    ))
+}
+
+fn rect_from_corners((x1, y1): (u32, u32), (x2, y2): (u32, u32)) -> Rect {
+    Rect {
+        x: x1.min(x2),
+        y: y1.min(y2),
+        width: x1.abs_diff(x2),
+        height: y1.abs_diff(y2),
+    }
+}
+
+fn overlay_style(rect: &Rect, natural_size: (u32, u32)) -> String {
+    let (width, height) = natural_size;
+    format!(
+        "left: {}%; top: {}%; width: {}%; height: {}%",
+        rect.x as f64 / width as f64 * 100.0,
+        rect.y as f64 / height as f64 * 100.0,
+        rect.width as f64 / width as f64 * 100.0,
+        rect.height as f64 / height as f64 * 100.0,
+    )
+}
+
+#[function_component(RegionEditor)]
+pub fn region_editor(props: &RegionEditorProps) -> Html {
+    let image_ref = use_node_ref();
+    // Natural size is known only after the image loads
+    let natural_size = use_state(|| None::<(u32, u32)>);
+    // Corners of the rectangle being drawn: (start, current)
+    let drag = use_state(|| None::<((u32, u32), (u32, u32))>);
+
+    let on_load = {
+        let image_ref = image_ref.clone();
+        let natural_size = natural_size.clone();
+        Callback::from(move |_: Event| {
+            if let Some(image) = image_ref.cast::<HtmlImageElement>() {
+                natural_size.set(Some((image.natural_width(), image.natural_height())));
+            }
+        })
+    };
+
+    let on_mouse_down = {
+        let image_ref = image_ref.clone();
+        let drag = drag.clone();
+        let disabled = props.disabled;
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            if disabled {
+                return;
+            }
+            if let Some(pos) = to_image_coords(&image_ref, &e) {
+                drag.set(Some((pos, pos)));
+            }
+        })
+    };
+
+    let on_mouse_move = {
+        let image_ref = image_ref.clone();
+        let drag = drag.clone();
+        Callback::from(move |e: MouseEvent| {
+            if let (Some((start, _)), Some(pos)) = (*drag, to_image_coords(&image_ref, &e)) {
+                drag.set(Some((start, pos)));
+            }
+        })
+    };
+
+    let on_mouse_up = {
+        let drag = drag.clone();
+        let regions = props.regions.clone();
+        let on_change = props.on_change.clone();
+        let kind = props.kind;
+        Callback::from(move |_: MouseEvent| {
+            if let Some((start, end)) = *drag {
+                let rect = rect_from_corners(start, end);
+                // A simple click is not a region
+                if rect.width > 0 && rect.height > 0 {
+                    let mut new_regions = match kind {
+                        RegionKind::Template => Vec::new(),
+                        RegionKind::Search | RegionKind::Exclusion => regions.clone(),
+                    };
+                    new_regions.push(rect);
+                    on_change.emit(new_regions);
+                }
+            }
+            drag.set(None);
+        })
+    };
+
+    let on_clear = {
+        let on_change = props.on_change.clone();
+        Callback::from(move |_: MouseEvent| on_change.emit(Vec::new()))
+    };
+
+    html! {
+        <div class={classes!(
+            "region-editor",
+            props.disabled.then_some("disabled"),
+            props.kind.class(),
+        )}>
+            <div
+                class="region-canvas"
+                onmousedown={on_mouse_down}
+                onmousemove={on_mouse_move}
+                onmouseup={on_mouse_up.clone()}
+                onmouseleave={on_mouse_up}
+            >
+                <img
+                    ref={&image_ref}
+                    src={props.image.clone()}
+                    alt="Main image for region selection"
+                    draggable="false"
+                    onload={on_load}
+                />
+                {
+                    if let Some(size) = *natural_size {
+                        html! {
+                            <>
+                                {
+                                    props.regions.iter().map(|r| html! {
+                                        <div class="region-overlay" style={overlay_style(r, size)} />
+                                    }).collect::<Html>()
+                                }
+                                {
+                                    if let Some((start, end)) = *drag {
+                                        html! {
+                                            <div
+                                                class="region-overlay drawing"
+                                                style={overlay_style(&rect_from_corners(start, end), size)}
+                                            />
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                            </>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+            </div>
+            <ul class="region-list">
+                {
+                    if props.regions.is_empty() {
+                        html! { <li>{props.kind.empty_text()}</li> }
+                    } else {
+                        props.regions.iter().enumerate().map(|(i, r)| {
+                            let on_remove = {
+                                let regions = props.regions.clone();
+                                let on_change = props.on_change.clone();
+                                Callback::from(move |_: MouseEvent| {
+                                    let mut new_regions = regions.clone();
+                                    new_regions.remove(i);
+                                    on_change.emit(new_regions);
+                                })
+                            };
+                            html! {
+                                <li>
+                                    {r.to_string()}
+                                    <button
+                                        class="region-remove"
+                                        onclick={on_remove}
+                                        disabled={props.disabled}
+                                    >{"×"}</button>
+                                </li>
+                            }
+                        }).collect::<Html>()
+                    }
+                }
+            </ul>
+            {
+                if props.regions.is_empty() {
+                    html! {}
+                } else {
+                    html! {
+                        <button class="edit-button" onclick={on_clear} disabled={props.disabled}>
+                            {props.kind.clear_text()}
+                        </button>
+                    }
+                }
+            }
+        </div>
+    }
+}
@@ -0,0 +1,768 @@
+use crate::components::search_results::template_color;
+use crate::image::{ErrorMap, Rect, SearchResults};
+use gloo::events::{EventListener, EventListenerOptions};
+use std::rc::Rc;
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlElement, HtmlImageElement};
+use yew::prelude::*;
+
+// Screen pixels per image pixel
+const MAX_SCALE: f64 = 64.0;
+// Image pixels are large enough to tell them apart
+const PIXEL_GRID_SCALE: f64 = 8.0;
+const WHEEL_ZOOM_FACTOR: f64 = 1.25;
+// A match shown from the list takes a third of the viewer, so its surroundings are visible too
+const MATCH_CONTEXT: f64 = 3.0;
+const MAX_CANVAS_HEIGHT: f64 = 600.0;
+const MINIMAP_WIDTH: f64 = 160.0;
+// The main image stays visible under the error heatmap
+const HEATMAP_OPACITY: f64 = 0.7;
+
+/**
+ * Part of the main image shown in the viewer.
+ * The image pixel (x, y) is drawn at ((x - left) * scale, (y - top) * scale) of the canvas.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Viewport {
+    image_width: f64,
+    image_height: f64,
+    canvas_width: f64,
+    canvas_height: f64,
+    left: f64,
+    top: f64,
+    scale: f64,
+}
+
+pub enum ViewportAction {
+    // The image or the canvas was (re)loaded, the view is kept when their sizes are the same
+    Resize {
+        image_width: u32,
+        image_height: u32,
+        canvas_width: u32,
+        canvas_height: u32,
+    },
+    Fit,
+    // Around a point of the canvas, which stays at the same pixel of the image
+    Zoom {
+        x: f64,
+        y: f64,
+        factor: f64,
+    },
+    // By canvas pixels, e.g., the distance the mouse was dragged
+    Pan {
+        dx: f64,
+        dy: f64,
+    },
+    CenterOn {
+        x: f64,
+        y: f64,
+    },
+    Show(Rect),
+}
+
+impl Viewport {
+    fn is_empty(&self) -> bool {
+        self.image_width <= 0.0 || self.image_height <= 0.0 || self.canvas_width <= 0.0
+    }
+
+    fn fit_scale(&self) -> f64 {
+        (self.canvas_width / self.image_width).min(self.canvas_height / self.image_height)
+    }
+
+    fn visible_width(&self) -> f64 {
+        self.canvas_width / self.scale
+    }
+
+    fn visible_height(&self) -> f64 {
+        self.canvas_height / self.scale
+    }
+
+    // Part of the image is outside of the canvas
+    fn is_zoomed_in(&self) -> bool {
+        self.visible_width() < self.image_width || self.visible_height() < self.image_height
+    }
+
+    fn fit(self) -> Viewport {
+        Viewport {
+            scale: self.fit_scale(),
+            ..self
+        }
+        .clamped()
+    }
+
+    fn centered_on(self, x: f64, y: f64) -> Viewport {
+        Viewport {
+            left: x - self.visible_width() / 2.0,
+            top: y - self.visible_height() / 2.0,
+            ..self
+        }
+        .clamped()
+    }
+
+    // Zooming out stops at the whole image, a smaller image than the canvas is not shrunk
+    fn clamped(self) -> Viewport {
+        let scale = self
+            .scale
+            .clamp(self.fit_scale().min(1.0), MAX_SCALE.max(self.fit_scale()));
+        let clamp_offset = |offset: f64, visible: f64, size: f64| {
+            if visible >= size {
+                // Centered when the image is smaller than the canvas
+                (size - visible) / 2.0
+            } else {
+                offset.clamp(0.0, size - visible)
+            }
+        };
+        Viewport {
+            left: clamp_offset(self.left, self.canvas_width / scale, self.image_width),
+            top: clamp_offset(self.top, self.canvas_height / scale, self.image_height),
+            scale,
+            ..self
+        }
+    }
+
+    fn canvas_point(&self, x: f64, y: f64) -> (f64, f64) {
+        ((x - self.left) * self.scale, (y - self.top) * self.scale)
+    }
+
+    fn image_point(&self, x: f64, y: f64) -> (f64, f64) {
+        (self.left + x / self.scale, self.top + y / self.scale)
+    }
+}
+
+impl Reducible for Viewport {
+    type Action = ViewportAction;
+
+    fn reduce(self: Rc<Self>, action: ViewportAction) -> Rc<Self> {
+        let viewport = *self;
+        let new_viewport = match action {
+            ViewportAction::Resize {
+                image_width,
+                image_height,
+                canvas_width,
+                canvas_height,
+            } => {
+                let resized = Viewport {
+                    image_width: image_width as f64,
+                    image_height: image_height as f64,
+                    canvas_width: canvas_width as f64,
+                    canvas_height: canvas_height as f64,
+                    ..viewport
+                };
+                if resized.is_empty() {
+                    return Rc::new(resized);
+                }
+                // Scrubbing through frames of the same size keeps the zoomed part
+                if resized == viewport && viewport.scale > 0.0 {
+                    return self;
+                }
+                resized.fit()
+            }
+            _ if viewport.is_empty() => return self,
+            ViewportAction::Fit => viewport.fit(),
+            ViewportAction::Zoom { x, y, factor } => {
+                let (image_x, image_y) = viewport.image_point(x, y);
+                let zoomed = Viewport {
+                    scale: viewport.scale * factor,
+                    ..viewport
+                }
+                .clamped();
+                Viewport {
+                    left: image_x - x / zoomed.scale,
+                    top: image_y - y / zoomed.scale,
+                    ..zoomed
+                }
+                .clamped()
+            }
+            ViewportAction::Pan { dx, dy } => Viewport {
+                left: viewport.left - dx / viewport.scale,
+                top: viewport.top - dy / viewport.scale,
+                ..viewport
+            }
+            .clamped(),
+            ViewportAction::CenterOn { x, y } => viewport.centered_on(x, y),
+            ViewportAction::Show(rect) => {
+                let width = rect.width.max(1) as f64 * MATCH_CONTEXT;
+                let height = rect.height.max(1) as f64 * MATCH_CONTEXT;
+                Viewport {
+                    scale: (viewport.canvas_width / width).min(viewport.canvas_height / height),
+                    ..viewport
+                }
+                .clamped()
+                .centered_on(
+                    rect.x as f64 + rect.width as f64 / 2.0,
+                    rect.y as f64 + rect.height as f64 / 2.0,
+                )
+            }
+        };
+        Rc::new(new_viewport)
+    }
+}
+
+/// Match selected in the list of matches: (template index, match index)
+pub type MatchId = (usize, usize);
+
+#[derive(Properties, PartialEq)]
+pub struct ResultViewerProps {
+    pub image: String,
+    pub results: Rc<Vec<SearchResults>>,
+    // Shared with the list of matches, so it can zoom to a match
+    pub viewport: UseReducerHandle<Viewport>,
+    #[prop_or_default]
+    pub selected: Option<MatchId>,
+    // Errors of the positions, drawn with the top-left corner of the main image
+    #[prop_or_default]
+    pub heatmap: Option<Rc<ErrorMap>>,
+}
+
+fn context_2d(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d, JsValue> {
+    canvas
+        .get_context("2d")?
+        .ok_or_else(|| JsValue::from_str("canvas has no 2D context"))?
+        .dyn_into()
+        .map_err(JsValue::from)
+}
+
+// Converts mouse position to pixels of the canvas, which may be scaled by CSS
+fn to_canvas_coords(canvas: &HtmlCanvasElement, event: &MouseEvent) -> Option<(f64, f64)> {
+    let bounds = canvas.get_bounding_client_rect();
+    if bounds.width() <= 0.0 || bounds.height() <= 0.0 {
+        return None;
+    }
+    Some((
+        (event.client_x() as f64 - bounds.left()) * canvas.width() as f64 / bounds.width(),
+        (event.client_y() as f64 - bounds.top()) * canvas.height() as f64 / bounds.height(),
+    ))
+}
+
+// The heatmap is rendered once, so zooming and panning only scale it
+fn render_heatmap(landscape: &ErrorMap) -> Result<HtmlCanvasElement, JsValue> {
+    let canvas: HtmlCanvasElement = gloo::utils::document()
+        .create_element("canvas")?
+        .dyn_into()
+        .map_err(JsValue::from)?;
+    canvas.set_width(landscape.get_width());
+    canvas.set_height(landscape.get_height());
+    let heatmap = landscape
+        .to_heatmap()
+        .to_image_data::<u8>()
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let pixels = heatmap.get_pixels(0, 0, (heatmap.width * heatmap.height) as usize);
+    let image_data = web_sys::ImageData::new_with_u8_clamped_array_and_sh(
+        Clamped(pixels),
+        heatmap.width,
+        heatmap.height,
+    )?;
+    context_2d(&canvas)?.put_image_data(&image_data, 0.0, 0.0)?;
+    Ok(canvas)
+}
+
+fn draw_pixel_grid(context: &CanvasRenderingContext2d, viewport: &Viewport) {
+    let (left, top) = viewport.canvas_point(0.0, 0.0);
+    let (right, bottom) = viewport.canvas_point(viewport.image_width, viewport.image_height);
+    let first_x = viewport.left.max(0.0).floor() as u32;
+    let last_x = (viewport.left + viewport.visible_width()).min(viewport.image_width) as u32;
+    let first_y = viewport.top.max(0.0).floor() as u32;
+    let last_y = (viewport.top + viewport.visible_height()).min(viewport.image_height) as u32;
+    context.begin_path();
+    for x in first_x..=last_x {
+        let (canvas_x, _) = viewport.canvas_point(x as f64, 0.0);
+        // Lines at the middle of canvas pixels are sharp
+        context.move_to(canvas_x.round() + 0.5, top.max(0.0));
+        context.line_to(canvas_x.round() + 0.5, bottom.min(viewport.canvas_height));
+    }
+    for y in first_y..=last_y {
+        let (_, canvas_y) = viewport.canvas_point(0.0, y as f64);
+        context.move_to(left.max(0.0), canvas_y.round() + 0.5);
+        context.line_to(right.min(viewport.canvas_width), canvas_y.round() + 0.5);
+    }
+    context.set_line_width(1.0);
+    context.set_stroke_style_str("rgba(128, 128, 128, 0.5)");
+    context.stroke();
+}
+
+fn draw_matches(
+    context: &CanvasRenderingContext2d,
+    viewport: &Viewport,
+    results: &[SearchResults],
+    selected: Option<MatchId>,
+) {
+    for (template_index, search_results) in results.iter().enumerate() {
+        let color = template_color(template_index);
+        context.set_fill_style_str(color);
+        context.set_stroke_style_str(color);
+        let width = search_results.get_template_width() as f64 * viewport.scale;
+        let height = search_results.get_template_height() as f64 * viewport.scale;
+        for (match_index, m) in search_results.get_matches().iter().enumerate() {
+            let (x, y) = viewport.canvas_point(m.x as f64, m.y as f64);
+            // Hundreds of matches are mostly outside of a zoomed in view
+            if x > viewport.canvas_width
+                || y > viewport.canvas_height
+                || x + width < 0.0
+                || y + height < 0.0
+            {
+                continue;
+            }
+            let is_selected = selected == Some((template_index, match_index));
+            context.set_global_alpha(if is_selected { 0.4 } else { 0.2 });
+            context.fill_rect(x, y, width, height);
+            context.set_global_alpha(1.0);
+            context.set_line_width(if is_selected { 4.0 } else { 2.0 });
+            context.stroke_rect(x, y, width, height);
+        }
+    }
+}
+
+fn draw(
+    canvas: &HtmlCanvasElement,
+    image: &HtmlImageElement,
+    heatmap: Option<&HtmlCanvasElement>,
+    viewport: &Viewport,
+    results: &[SearchResults],
+    selected: Option<MatchId>,
+) -> Result<(), JsValue> {
+    let context = context_2d(canvas)?;
+    context.clear_rect(0.0, 0.0, viewport.canvas_width, viewport.canvas_height);
+    // Zoomed in pixels are sharp squares instead of blurred
+    context.set_image_smoothing_enabled(viewport.scale < 1.0);
+    let (x, y) = viewport.canvas_point(0.0, 0.0);
+    context.draw_image_with_html_image_element_and_dw_and_dh(
+        image,
+        x,
+        y,
+        viewport.image_width * viewport.scale,
+        viewport.image_height * viewport.scale,
+    )?;
+    if let Some(heatmap) = heatmap {
+        context.set_global_alpha(HEATMAP_OPACITY);
+        context.draw_image_with_html_canvas_element_and_dw_and_dh(
+            heatmap,
+            x,
+            y,
+            heatmap.width() as f64 * viewport.scale,
+            heatmap.height() as f64 * viewport.scale,
+        )?;
+        context.set_global_alpha(1.0);
+    }
+    if viewport.scale >= PIXEL_GRID_SCALE {
+        draw_pixel_grid(&context, viewport);
+    }
+    draw_matches(&context, viewport, results, selected);
+    Ok(())
+}
+
+fn draw_minimap(
+    minimap: &HtmlCanvasElement,
+    image: &HtmlImageElement,
+    viewport: &Viewport,
+) -> Result<(), JsValue> {
+    let context = context_2d(minimap)?;
+    let width = minimap.width() as f64;
+    let height = minimap.height() as f64;
+    context.clear_rect(0.0, 0.0, width, height);
+    context.set_image_smoothing_enabled(true);
+    context.draw_image_with_html_image_element_and_dw_and_dh(image, 0.0, 0.0, width, height)?;
+    let scale = width / viewport.image_width;
+    context.set_line_width(2.0);
+    context.set_stroke_style_str("#dc3545");
+    context.stroke_rect(
+        viewport.left.max(0.0) * scale,
+        viewport.top.max(0.0) * scale,
+        viewport.visible_width().min(viewport.image_width) * scale,
+        viewport.visible_height().min(viewport.image_height) * scale,
+    );
+    Ok(())
+}
+
+/// Main image with the matches drawn on a canvas, it can be zoomed by the mouse wheel and panned by dragging.
+#[function_component(ResultViewer)]
+pub fn result_viewer(props: &ResultViewerProps) -> Html {
+    let container_ref = use_node_ref();
+    let canvas_ref = use_node_ref();
+    let minimap_ref = use_node_ref();
+    let image_ref = use_node_ref();
+    // Last mouse position while dragging, in client coordinates
+    let drag = use_state(|| None::<(i32, i32)>);
+    let viewport = *props.viewport;
+
+    let on_load = {
+        let container_ref = container_ref.clone();
+        let image_ref = image_ref.clone();
+        let dispatcher = props.viewport.dispatcher();
+        Callback::from(move |_: Event| {
+            let (Some(container), Some(image)) = (
+                container_ref.cast::<HtmlElement>(),
+                image_ref.cast::<HtmlImageElement>(),
+            ) else {
+                return;
+            };
+            let (image_width, image_height) = (image.natural_width(), image.natural_height());
+            if image_width == 0 || image_height == 0 {
+                return;
+            }
+            let canvas_width = container.client_width().max(1) as u32;
+            let canvas_height = (canvas_width as f64 * image_height as f64 / image_width as f64)
+                .clamp(1.0, MAX_CANVAS_HEIGHT) as u32;
+            dispatcher.dispatch(ViewportAction::Resize {
+                image_width,
+                image_height,
+                canvas_width,
+                canvas_height,
+            });
+        })
+    };
+
+    {
+        // Yew listens to wheel events passively, so they could not prevent scrolling the page
+        let canvas_ref = canvas_ref.clone();
+        let dispatcher = props.viewport.dispatcher();
+        use_effect_with((), move |_| {
+            let listener = canvas_ref.cast::<HtmlCanvasElement>().map(|canvas| {
+                let target = canvas.clone();
+                EventListener::new_with_options(
+                    &target,
+                    "wheel",
+                    EventListenerOptions::enable_prevent_default(),
+                    move |event| {
+                        let Some(event) = event.dyn_ref::<web_sys::WheelEvent>() else {
+                            return;
+                        };
+                        event.prevent_default();
+                        let Some((x, y)) = to_canvas_coords(&canvas, event) else {
+                            return;
+                        };
+                        let factor = if event.delta_y() < 0.0 {
+                            WHEEL_ZOOM_FACTOR
+                        } else {
+                            1.0 / WHEEL_ZOOM_FACTOR
+                        };
+                        dispatcher.dispatch(ViewportAction::Zoom { x, y, factor });
+                    },
+                )
+            });
+            move || drop(listener)
+        });
+    }
+
+    let heatmap = use_memo(props.heatmap.clone(), |landscape| {
+        landscape.as_ref().and_then(|landscape| {
+            render_heatmap(landscape)
+                .inspect_err(|err| log::error!("Error drawing the error heatmap: {:?}", err))
+                .ok()
+        })
+    });
+
+    {
+        let canvas_ref = canvas_ref.clone();
+        let minimap_ref = minimap_ref.clone();
+        let image_ref = image_ref.clone();
+        use_effect_with(
+            (viewport, props.results.clone(), props.selected, heatmap),
+            move |(viewport, results, selected, heatmap)| {
+                if viewport.is_empty() {
+                    return;
+                }
+                let Some(image) = image_ref.cast::<HtmlImageElement>() else {
+                    return;
+                };
+                if let Some(canvas) = canvas_ref.cast::<HtmlCanvasElement>()
+                    && let Err(err) = draw(
+                        &canvas,
+                        &image,
+                        heatmap.as_ref().as_ref(),
+                        viewport,
+                        results,
+                        *selected,
+                    )
+                {
+                    log::error!("Error drawing the results: {:?}", err);
+                }
+                if let Some(minimap) = minimap_ref.cast::<HtmlCanvasElement>()
+                    && let Err(err) = draw_minimap(&minimap, &image, viewport)
+                {
+                    log::error!("Error drawing the minimap: {:?}", err);
+                }
+            },
+        );
+    }
+
+    let on_mouse_down = {
+        let drag = drag.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            drag.set(Some((e.client_x(), e.client_y())));
+        })
+    };
+
+    let on_mouse_move = {
+        let drag = drag.clone();
+        let dispatcher = props.viewport.dispatcher();
+        let canvas_ref = canvas_ref.clone();
+        Callback::from(move |e: MouseEvent| {
+            let (Some((last_x, last_y)), Some(canvas)) =
+                (*drag, canvas_ref.cast::<HtmlCanvasElement>())
+            else {
+                return;
+            };
+            // Client pixels may differ from canvas pixels when the canvas is scaled by CSS
+            let bounds = canvas.get_bounding_client_rect();
+            let ratio = if bounds.width() > 0.0 {
+                canvas.width() as f64 / bounds.width()
+            } else {
+                1.0
+            };
+            dispatcher.dispatch(ViewportAction::Pan {
+                dx: (e.client_x() - last_x) as f64 * ratio,
+                dy: (e.client_y() - last_y) as f64 * ratio,
+            });
+            drag.set(Some((e.client_x(), e.client_y())));
+        })
+    };
+
+    let on_mouse_up = {
+        let drag = drag.clone();
+        Callback::from(move |_: MouseEvent| drag.set(None))
+    };
+
+    let on_minimap_click = {
+        let minimap_ref = minimap_ref.clone();
+        let dispatcher = props.viewport.dispatcher();
+        Callback::from(move |e: MouseEvent| {
+            if let Some(minimap) = minimap_ref.cast::<HtmlCanvasElement>()
+                && let Some((x, y)) = to_canvas_coords(&minimap, &e)
+            {
+                let scale = viewport.image_width / minimap.width() as f64;
+                dispatcher.dispatch(ViewportAction::CenterOn {
+                    x: x * scale,
+                    y: y * scale,
+                });
+            }
+        })
+    };
+
+    let on_zoom = |factor: f64| {
+        let dispatcher = props.viewport.dispatcher();
+        Callback::from(move |_: MouseEvent| {
+            dispatcher.dispatch(ViewportAction::Zoom {
+                x: viewport.canvas_width / 2.0,
+                y: viewport.canvas_height / 2.0,
+                factor,
+            })
+        })
+    };
+
+    let on_fit = {
+        let dispatcher = props.viewport.dispatcher();
+        Callback::from(move |_: MouseEvent| dispatcher.dispatch(ViewportAction::Fit))
+    };
+
+    let minimap_height = if viewport.is_empty() {
+        0.0
+    } else {
+        MINIMAP_WIDTH * viewport.image_height / viewport.image_width
+    };
+
+    html! {
+        <div class="result-viewer">
+            <div class="viewer-toolbar">
+                <button class="edit-button" onclick={on_zoom(WHEEL_ZOOM_FACTOR)}>{"Zoom in"}</button>
+                <button class="edit-button" onclick={on_zoom(1.0 / WHEEL_ZOOM_FACTOR)}>{"Zoom out"}</button>
+                <button class="edit-button" onclick={on_fit}>{"Fit"}</button>
+                <span class="zoom-level">{format!("{:.0}%", viewport.scale * 100.0)}</span>
+            </div>
+            <div
+                ref={&container_ref}
+                class={classes!("viewer-canvas", drag.is_some().then_some("dragging"))}
+            >
+                <canvas
+                    ref={&canvas_ref}
+                    width={viewport.canvas_width.to_string()}
+                    height={viewport.canvas_height.to_string()}
+                    onmousedown={on_mouse_down}
+                    onmousemove={on_mouse_move}
+                    onmouseup={on_mouse_up.clone()}
+                    onmouseleave={on_mouse_up}
+                />
+                <canvas
+                    ref={&minimap_ref}
+                    class={classes!("minimap", (!viewport.is_zoomed_in()).then_some("hidden"))}
+                    width={MINIMAP_WIDTH.to_string()}
+                    height={minimap_height.round().to_string()}
+                    onclick={on_minimap_click}
+                />
+                // Source of the canvas drawing, it is not displayed itself
+                <img
+                    ref={&image_ref}
+                    src={props.image.clone()}
+                    alt="Main image with matches"
+                    class="result-main-image"
+                    onload={on_load}
+                />
+            </div>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(
+        image_width: u32,
+        image_height: u32,
+        canvas_width: u32,
+        canvas_height: u32,
+    ) -> Viewport {
+        *Rc::new(Viewport::default()).reduce(ViewportAction::Resize {
+            image_width,
+            image_height,
+            canvas_width,
+            canvas_height,
+        })
+    }
+
+    fn reduce(viewport: Viewport, action: ViewportAction) -> Viewport {
+        *Rc::new(viewport).reduce(action)
+    }
+
+    #[test]
+    fn test_fit() {
+        let fitted = viewport(200, 100, 400, 300);
+        assert_eq!(fitted.scale, 2.0);
+        // The rest of the canvas is split evenly above and below the image
+        assert_eq!((fitted.left, fitted.top), (0.0, -25.0));
+        assert!(!fitted.is_zoomed_in());
+
+        let zoomed = reduce(
+            fitted,
+            ViewportAction::Zoom {
+                x: 10.0,
+                y: 10.0,
+                factor: 4.0,
+            },
+        );
+        assert!(zoomed.is_zoomed_in());
+        assert_eq!(reduce(zoomed, ViewportAction::Fit), fitted);
+        // A new frame of the same size keeps the zoomed part
+        let resized = reduce(
+            zoomed,
+            ViewportAction::Resize {
+                image_width: 200,
+                image_height: 100,
+                canvas_width: 400,
+                canvas_height: 300,
+            },
+        );
+        assert_eq!(resized, zoomed);
+
+        // Small images are enlarged and large ones shrunk to fit the canvas
+        assert_eq!(viewport(10, 10, 400, 300).scale, 30.0);
+        assert_eq!(viewport(1000, 500, 400, 300).scale, 0.4);
+    }
+
+    #[test]
+    fn test_zoom_around_cursor() {
+        let fitted = viewport(200, 100, 200, 100);
+        let zoomed = reduce(
+            fitted,
+            ViewportAction::Zoom {
+                x: 50.0,
+                y: 40.0,
+                factor: 2.0,
+            },
+        );
+        assert_eq!(zoomed.scale, 2.0);
+        // The pixel under the cursor stays under it
+        assert_eq!(
+            zoomed.image_point(50.0, 40.0),
+            fitted.image_point(50.0, 40.0)
+        );
+        assert_eq!((zoomed.left, zoomed.top), (25.0, 20.0));
+
+        // Zooming is limited to the whole image and to MAX_SCALE
+        let out = reduce(
+            zoomed,
+            ViewportAction::Zoom {
+                x: 0.0,
+                y: 0.0,
+                factor: 0.01,
+            },
+        );
+        assert_eq!(out, fitted);
+        let far_in = reduce(
+            zoomed,
+            ViewportAction::Zoom {
+                x: 0.0,
+                y: 0.0,
+                factor: 1000.0,
+            },
+        );
+        assert_eq!(far_in.scale, MAX_SCALE);
+    }
+
+    #[test]
+    fn test_pan_clamped_to_image() {
+        let zoomed = reduce(
+            viewport(200, 100, 200, 100),
+            ViewportAction::Zoom {
+                x: 50.0,
+                y: 40.0,
+                factor: 2.0,
+            },
+        );
+        let panned = reduce(
+            zoomed,
+            ViewportAction::Pan {
+                dx: -20.0,
+                dy: 10.0,
+            },
+        );
+        assert_eq!((panned.left, panned.top), (35.0, 15.0));
+        // The visible part never leaves the image
+        let right = reduce(
+            zoomed,
+            ViewportAction::Pan {
+                dx: -1000.0,
+                dy: -1000.0,
+            },
+        );
+        assert_eq!((right.left, right.top), (100.0, 50.0));
+        let left = reduce(
+            zoomed,
+            ViewportAction::Pan {
+                dx: 1000.0,
+                dy: 1000.0,
+            },
+        );
+        assert_eq!((left.left, left.top), (0.0, 0.0));
+        // Nothing to pan when the whole image is visible
+        let fitted = viewport(200, 100, 400, 300);
+        assert_eq!(
+            reduce(fitted, ViewportAction::Pan { dx: 50.0, dy: 50.0 }),
+            fitted
+        );
+    }
+
+    #[test]
+    fn test_show_and_center_on() {
+        let fitted = viewport(200, 100, 200, 100);
+        let shown = reduce(
+            fitted,
+            ViewportAction::Show(Rect {
+                x: 100,
+                y: 40,
+                width: 10,
+                height: 5,
+            }),
+        );
+        // The match takes a third of the viewer and is in its center
+        assert_eq!(shown.scale, 100.0 / 15.0);
+        let (x, y) = shown.canvas_point(105.0, 42.5);
+        assert!(
+            (x - 100.0).abs() < 1e-9 && (y - 50.0).abs() < 1e-9,
+            "{x}, {y}"
+        );
+        // A corner cannot be centered without showing what is outside of the image
+        let corner = reduce(shown, ViewportAction::CenterOn { x: 0.0, y: 0.0 });
+        assert_eq!((corner.left, corner.top), (0.0, 0.0));
+    }
+}
@@ -1,44 +1,228 @@
 use crate::components::image_input::ImageInput;
-use web_sys::{FileList, HtmlInputElement};
+use crate::components::presets::PresetPicker;
+use crate::components::region_editor::{RegionEditor, RegionKind};
+use crate::config::{Channels, ErrorLandscape, Metric, SearchConfig, ThresholdMode, ThresholdUnit};
+use crate::error::Error;
+use crate::image::Rect;
+use crate::presets::Preset;
+use crate::{PasteTarget, UploadedImage};
+use std::str::FromStr;
+use web_sys::{FileList, HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement};
 use yew::prelude::*;
 
 #[derive(Properties, PartialEq)]
 pub struct SearchParamsProps {
-    pub main_image: Option<String>,
-    pub search_image: Option<String>,
-    pub max_mse: f64,
-    pub max_results: u16,
+    pub main_images: Vec<UploadedImage>,
+    pub search_images: Vec<UploadedImage>,
+    pub mask_image: Option<UploadedImage>,
+    pub config: SearchConfig,
+    pub user_presets: Vec<Preset>,
     pub disabled: bool,
-    pub on_max_mse_change: Callback<f64>,
-    pub on_max_results_change: Callback<u16>,
+    pub on_config_change: Callback<SearchConfig>,
+    pub on_user_presets_change: Callback<Vec<Preset>>,
+    pub on_mask_image_upload: Callback<FileList>,
+    pub on_mask_image_remove: Callback<()>,
     pub on_main_image_upload: Callback<FileList>,
     pub on_search_image_upload: Callback<FileList>,
+    pub paste_target: PasteTarget,
+    pub on_paste_target_change: Callback<PasteTarget>,
+}
+
+fn parse_input<T: FromStr>(e: &InputEvent) -> Option<T> {
+    let value = e.target_dyn_into::<HtmlInputElement>()?.value();
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            log::warn!("ignoring invalid number {:?}", value);
+            None
+        }
+    }
 }
 
 #[function_component(SearchParams)]
 pub fn search_params(props: &SearchParamsProps) -> Html {
-    let on_max_mse_change = props.on_max_mse_change.clone();
-    let on_max_results_change = props.on_max_results_change.clone();
-
-    let handle_mse_change = Callback::from(move |e: InputEvent| {
-        let value = e
-            .target_dyn_into::<HtmlInputElement>()
-            .unwrap()
-            .value()
-            .parse::<f64>()
-            .unwrap();
-        on_max_mse_change.emit(value);
-    });
+    // Values being typed can be temporarily unparsable (e.g., an empty field), they are just skipped.
+    // Out-of-range values are reported by the search itself.
+    let handle_mse_change = {
+        let config = props.config.clone();
+        let on_config_change = props.on_config_change.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(value) = parse_input::<f64>(&e) {
+                on_config_change.emit(SearchConfig {
+                    max_mse: config.threshold_unit.to_mse(value),
+                    ..config.clone()
+                });
+            }
+        })
+    };
 
-    let handle_results_change = Callback::from(move |e: InputEvent| {
-        let value = e
-            .target_dyn_into::<HtmlInputElement>()
-            .unwrap()
-            .value()
-            .parse::<u16>()
-            .unwrap();
-        on_max_results_change.emit(value);
-    });
+    let handle_threshold_unit_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |e: Event| {
+            let threshold_unit = match e.target_dyn_into::<HtmlSelectElement>().map(|s| s.value()) {
+                Some(value) if value == "psnr" => ThresholdUnit::Psnr,
+                Some(value) if value == "rmse" => ThresholdUnit::Rmse,
+                Some(value) if value == "max_deviation" => ThresholdUnit::MaxDeviation,
+                _ => ThresholdUnit::Mse,
+            };
+            SearchConfig {
+                threshold_unit,
+                ..config.clone()
+            }
+        })
+    };
+
+    let handle_threshold_mode_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |e: Event| {
+            let threshold_mode = match e.target_dyn_into::<HtmlSelectElement>().map(|s| s.value()) {
+                Some(value) if value == "automatic" => ThresholdMode::Automatic,
+                Some(value) if value == "best_k" => ThresholdMode::BestK,
+                _ => ThresholdMode::Fixed,
+            };
+            SearchConfig {
+                threshold_mode,
+                ..config.clone()
+            }
+        })
+    };
+
+    let handle_results_change = {
+        let config = props.config.clone();
+        let on_config_change = props.on_config_change.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(max_results) = parse_input::<u16>(&e) {
+                on_config_change.emit(SearchConfig {
+                    max_results,
+                    ..config.clone()
+                });
+            }
+        })
+    };
+
+    let handle_channels_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |e: Event| {
+            let channels = match e.target_dyn_into::<HtmlSelectElement>().map(|s| s.value()) {
+                Some(value) if value == "rgb" => Channels::Rgb,
+                _ => Channels::Rgba,
+            };
+            SearchConfig {
+                channels,
+                ..config.clone()
+            }
+        })
+    };
+
+    let handle_metric_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |e: Event| {
+            let metric = match e.target_dyn_into::<HtmlSelectElement>().map(|s| s.value()) {
+                Some(value) if value == "matching_pixels" => Metric::MatchingPixels,
+                Some(value) if value == "normalized_channels" => Metric::NormalizedChannels,
+                Some(value) if value == "normalized_luminance" => Metric::NormalizedLuminance,
+                _ => Metric::Mse,
+            };
+            SearchConfig {
+                metric,
+                // Only the mean squared error has other units than percent
+                threshold_unit: if metric == Metric::Mse {
+                    config.threshold_unit
+                } else {
+                    ThresholdUnit::Mse
+                },
+                ..config.clone()
+            }
+        })
+    };
+
+    let handle_pixel_tolerance_change = {
+        let config = props.config.clone();
+        let on_config_change = props.on_config_change.clone();
+        Callback::from(move |e: InputEvent| {
+            if let Some(pixel_tolerance_percent) = parse_input::<f64>(&e) {
+                on_config_change.emit(SearchConfig {
+                    pixel_tolerance: pixel_tolerance_percent / 100.0,
+                    ..config.clone()
+                });
+            }
+        })
+    };
+
+    let handle_error_landscape_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |e: Event| {
+            let error_landscape = match e.target_dyn_into::<HtmlSelectElement>().map(|s| s.value())
+            {
+                Some(value) if value == "capped" => ErrorLandscape::Capped,
+                Some(value) if value == "exact" => ErrorLandscape::Exact,
+                _ => ErrorLandscape::Off,
+            };
+            SearchConfig {
+                error_landscape,
+                ..config.clone()
+            }
+        })
+    };
+
+    let handle_apply_metadata_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |e: Event| SearchConfig {
+            apply_image_metadata: e
+                .target_dyn_into::<HtmlInputElement>()
+                .is_some_and(|input| input.checked()),
+            ..config.clone()
+        })
+    };
+
+    let on_regions_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |regions| SearchConfig {
+            regions,
+            ..config.clone()
+        })
+    };
+    let on_template_region_change = {
+        let config = props.config.clone();
+        props
+            .on_config_change
+            .reform(move |regions: Vec<Rect>| SearchConfig {
+                template_region: regions.first().copied(),
+                ..config.clone()
+            })
+    };
+    let handle_exclude_template_region_change = {
+        let config = props.config.clone();
+        props.on_config_change.reform(move |e: Event| SearchConfig {
+            exclude_template_region: e
+                .target_dyn_into::<HtmlInputElement>()
+                .is_some_and(|input| input.checked()),
+            ..config.clone()
+        })
+    };
+    let on_excluded_regions_change = {
+        let config = props.config.clone();
+        props
+            .on_config_change
+            .reform(move |excluded_regions| SearchConfig {
+                excluded_regions,
+                ..config.clone()
+            })
+    };
+
+    let unit = props.config.threshold_unit;
+    let threshold = unit.from_mse(props.config.max_mse);
+    // Rounded, so converting the typed value back and forth doesn't change it
+    let threshold_value = if threshold.is_finite() {
+        ((threshold * 1e4).round() / 1e4).to_string()
+    } else {
+        String::new()
+    };
+    let (step, max) = match unit {
+        ThresholdUnit::Mse => ("0.1", "100"),
+        ThresholdUnit::Psnr => ("1", "100"),
+        ThresholdUnit::Rmse | ThresholdUnit::MaxDeviation => ("1", "256"),
+    };
 
     html! {
         <>
@@ -49,50 +233,181 @@ pub fn search_params(props: &SearchParamsProps) -> Html {
                     input_id="mainImageInput"
                     preview_id="mainImagePreview"
                     on_upload={props.on_main_image_upload.clone()}
-                    image={props.main_image.clone()}
-                    help={None}
+                    images={props.main_images.clone()}
+                    help={Some(main_image_help())}
                     disabled={props.disabled}
+                    multiple={true}
+                    apply_metadata={props.config.apply_image_metadata}
+                    is_paste_target={props.paste_target == PasteTarget::MainImages}
+                    on_paste_target={Some(props.on_paste_target_change.reform(|_| PasteTarget::MainImages))}
                 />
                 <ImageInput
-                    label="Image to search"
+                    label="Images to search"
                     input_id="searchImageInput"
                     preview_id="searchImagePreview"
                     on_upload={props.on_search_image_upload.clone()}
-                    image={props.search_image.clone()}
+                    images={props.search_images.clone()}
                     help={Some(image_search_help())}
                     disabled={props.disabled}
+                    multiple={true}
+                    apply_metadata={props.config.apply_image_metadata}
+                    is_paste_target={props.paste_target == PasteTarget::SearchImages}
+                    on_paste_target={Some(props.on_paste_target_change.reform(|_| PasteTarget::SearchImages))}
                 />
             </div>
 
+            {
+                // In batch mode, regions are drawn on the first main image and apply to all of them
+                if let Some(main_image) = props.main_images.first() {
+                    html! {
+                        <>
+                            <h2>{"Template from the main image"}</h2>
+                            <RegionEditor
+                                image={main_image.url.clone()}
+                                regions={props.config.template_region.iter().copied().collect::<Vec<_>>()}
+                                on_change={on_template_region_change}
+                                disabled={props.disabled}
+                                kind={RegionKind::Template}
+                            />
+                            <label class="template-region-options">
+                                <input
+                                    type="checkbox"
+                                    id="excludeTemplateRegionInput"
+                                    checked={props.config.exclude_template_region}
+                                    onchange={handle_exclude_template_region_change}
+                                    disabled={props.disabled}
+                                />
+                                {"Find only the other occurrences (matches overlapping the drawn template are discarded)"}
+                            </label>
+                            <h2>{"Search regions"}</h2>
+                            <RegionEditor
+                                image={main_image.url.clone()}
+                                regions={props.config.regions.clone()}
+                                on_change={on_regions_change}
+                                disabled={props.disabled}
+                            />
+                            <h2>{"Excluded areas"}</h2>
+                            <div class="image-inputs">
+                                <RegionEditor
+                                    image={main_image.url.clone()}
+                                    regions={props.config.excluded_regions.clone()}
+                                    on_change={on_excluded_regions_change}
+                                    disabled={props.disabled}
+                                    kind={RegionKind::Exclusion}
+                                />
+                                <ImageInput
+                                    label="Mask image (optional)"
+                                    input_id="maskImageInput"
+                                    preview_id="maskImagePreview"
+                                    on_upload={props.on_mask_image_upload.clone()}
+                                    images={props.mask_image.iter().cloned().collect::<Vec<_>>()}
+                                    help={Some(mask_help(props))}
+                                    disabled={props.disabled}
+                                    apply_metadata={props.config.apply_image_metadata}
+                                />
+                            </div>
+                        </>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+
             <h2>{"Settings"}</h2>
+            <PresetPicker
+                config={props.config.clone()}
+                user_presets={props.user_presets.clone()}
+                disabled={props.disabled}
+                on_config_change={props.on_config_change.clone()}
+                on_user_presets_change={props.on_user_presets_change.clone()}
+            />
             <div class="settings">
-                <label class="settings-item">
-                    <h3>{"Maximum difference (%)"}</h3>
+                <div class="settings-item">
+                    <h3>{"Maximum difference"}</h3>
                     <input
                         type="number"
                         id="maxMseInput"
-                        value={(props.max_mse * 100.0).to_string()}
+                        value={threshold_value}
+                        placeholder={(unit == ThresholdUnit::Psnr).then_some("∞")}
                         oninput={handle_mse_change}
-                        disabled={props.disabled}
-                        step="0.1"
+                        disabled={props.disabled || props.config.threshold_mode != ThresholdMode::Fixed}
+                        step={step}
                         min="0"
-                        max="100"
+                        max={max}
                     />
-                    <span class="unit">{"%"}</span>
+                    <select
+                        class="unit"
+                        id="thresholdUnitInput"
+                        onchange={handle_threshold_unit_change}
+                        disabled={props.disabled || props.config.metric != Metric::Mse}
+                    >
+                        <option value="mse" selected={unit == ThresholdUnit::Mse}>{"% MSE"}</option>
+                        <option value="psnr" selected={unit == ThresholdUnit::Psnr}>{"dB PSNR (minimum)"}</option>
+                        <option value="rmse" selected={unit == ThresholdUnit::Rmse}>{"levels RMSE"}</option>
+                        <option value="max_deviation" selected={unit == ThresholdUnit::MaxDeviation}>{"levels per subpixel"}</option>
+                    </select>
+                    <select
+                        class="threshold-mode"
+                        id="thresholdModeInput"
+                        onchange={handle_threshold_mode_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="fixed" selected={props.config.threshold_mode == ThresholdMode::Fixed}>{"Fixed"}</option>
+                        <option value="automatic" selected={props.config.threshold_mode == ThresholdMode::Automatic}>{"Automatic"}</option>
+                        <option value="best_k" selected={props.config.threshold_mode == ThresholdMode::BestK}>{"None, best results only"}</option>
+                    </select>
                     <ul class="settings-hint">
-                        <li><a href="https://en.wikipedia.org/wiki/Mean_squared_error" target="_blank">{"Mean squared error"}</a>{" threshold"}</li>
-                        <li>{"0% - exact match"}</li>
-                        <li>{"100% - any difference"}</li>
-                        <li>{"Alpha channel is also considered as a color component."}</li>
+                        {
+                            match unit {
+                                ThresholdUnit::Mse if props.config.metric == Metric::MatchingPixels => html! {
+                                    <>
+                                        <li>{"Percent of the template pixels that may differ by more than the pixel tolerance"}</li>
+                                        <li>{"0% - every pixel within the tolerance"}</li>
+                                    </>
+                                },
+                                ThresholdUnit::Mse if props.config.metric != Metric::Mse => html! {
+                                    <>
+                                        <li>{"Normalized error: 0% - identical up to brightness and contrast, 50% - unrelated, 100% - inverted"}</li>
+                                    </>
+                                },
+                                ThresholdUnit::Mse => html! {
+                                    <>
+                                        <li><a href="https://en.wikipedia.org/wiki/Mean_squared_error" target="_blank">{"Mean squared error"}</a>{" threshold"}</li>
+                                        <li>{"0% - exact match"}</li>
+                                        <li>{"100% - any difference"}</li>
+                                    </>
+                                },
+                                ThresholdUnit::Psnr => html! {
+                                    <>
+                                        <li><a href="https://en.wikipedia.org/wiki/Peak_signal-to-noise_ratio" target="_blank">{"Peak signal-to-noise ratio"}</a>{" of a match at least"}</li>
+                                        <li>{"Higher values are stricter, ∞ is an exact match."}</li>
+                                    </>
+                                },
+                                ThresholdUnit::Rmse => html! {
+                                    <>
+                                        <li>{"Root mean squared error in levels of 8-bit colors (0-255)"}</li>
+                                        <li>{"0 - exact match"}</li>
+                                    </>
+                                },
+                                ThresholdUnit::MaxDeviation => html! {
+                                    <>
+                                        <li>{"No color or alpha value may differ by more levels (0-255)."}</li>
+                                        <li>{"0 - exact match"}</li>
+                                    </>
+                                },
+                            }
+                        }
                         <li>{"Low values usually cause faster search due to optimizations."}</li>
+                        <li>{"Automatic picks the value separating the best positions from the rest, it is shown in the search summary."}</li>
+                        <li>{"Without a threshold, the best positions are found whatever their differences, as many as the maximum number of results."}</li>
                     </ul>
-                </label>
+                </div>
                 <label class="settings-item">
                     <h3>{"Maximum number of results"}</h3>
                     <input
                         type="number"
                         id="maxResultsInput"
-                        value={props.max_results.to_string()}
+                        value={props.config.max_results.to_string()}
                         oninput={handle_results_change}
                         disabled={props.disabled}
                         step="1"
@@ -104,7 +419,183 @@ pub fn search_params(props: &SearchParamsProps) -> Html {
                         <li>{"When there are more matches, the most relevant are shown."}</li>
                     </ul>
                 </label>
+                <div class="settings-item">
+                    <h3>{"Score"}</h3>
+                    <select
+                        id="metricInput"
+                        onchange={handle_metric_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="mse" selected={props.config.metric == Metric::Mse}>{"Mean squared error"}</option>
+                        <option value="matching_pixels" selected={props.config.metric == Metric::MatchingPixels}>{"Matching pixels"}</option>
+                        <option value="normalized_channels" selected={props.config.metric == Metric::NormalizedChannels}>{"Normalized brightness and contrast"}</option>
+                        <option value="normalized_luminance" selected={props.config.metric == Metric::NormalizedLuminance}>{"Normalized luminance"}</option>
+                    </select>
+                    {
+                        if props.config.metric == Metric::MatchingPixels {
+                            html! {
+                                <label class="pixel-tolerance">
+                                    {"Pixel tolerance "}
+                                    <input
+                                        type="number"
+                                        id="pixelToleranceInput"
+                                        value={(props.config.pixel_tolerance * 100.0).to_string()}
+                                        oninput={handle_pixel_tolerance_change}
+                                        disabled={props.disabled}
+                                        step="0.1"
+                                        min="0"
+                                        max="100"
+                                    />
+                                    <span class="unit">{"% MSE"}</span>
+                                </label>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    <ul class="settings-hint">
+                        <li>{"Matching pixels counts the pixels within the tolerance, so a cursor or a tooltip over a part of the template doesn't ruin the match."}</li>
+                        <li>{"Normalized scores ignore differences of brightness and contrast, e.g., between dark and light themes or lighting of photos. Luminance ignores also the tint."}</li>
+                    </ul>
+                </div>
+                <label class="settings-item">
+                    <h3>{"Compared channels"}</h3>
+                    <select
+                        id="channelsInput"
+                        onchange={handle_channels_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="rgba" selected={props.config.channels == Channels::Rgba}>{"Color and transparency"}</option>
+                        <option value="rgb" selected={props.config.channels == Channels::Rgb}>{"Color only"}</option>
+                    </select>
+                    <ul class="settings-hint">
+                        <li>{"Alpha channel is considered as a color component unless only color is compared."}</li>
+                    </ul>
+                </label>
+                <label class="settings-item">
+                    <h3>{"Image metadata"}</h3>
+                    <input
+                        type="checkbox"
+                        id="applyImageMetadataInput"
+                        checked={props.config.apply_image_metadata}
+                        onchange={handle_apply_metadata_change}
+                        disabled={props.disabled}
+                    />
+                    {"Apply orientation and color profiles"}
+                    <ul class="settings-hint">
+                        <li>{"Photos are rotated by their EXIF orientation and their colors are converted to sRGB, like in photo viewers."}</li>
+                        <li>{"Turn it off for comparing the pixels exactly as they are stored in the files."}</li>
+                    </ul>
+                </label>
+                <label class="settings-item">
+                    <h3>{"Error heatmap"}</h3>
+                    <select
+                        id="errorLandscapeInput"
+                        onchange={handle_error_landscape_change}
+                        disabled={props.disabled}
+                    >
+                        <option value="off" selected={props.config.error_landscape == ErrorLandscape::Off}>{"Off"}</option>
+                        <option value="capped" selected={props.config.error_landscape == ErrorLandscape::Capped}>{"Capped at the maximum difference"}</option>
+                        <option value="exact" selected={props.config.error_landscape == ErrorLandscape::Exact}>{"Exact (slower)"}</option>
+                    </select>
+                    <ul class="settings-hint">
+                        <li>{"Records the difference at every position, to show how distinctive the templates are."}</li>
+                        <li>{"Exact differences cannot be rejected early, so the search takes longer."}</li>
+                    </ul>
+                </label>
             </div>
+            <ConfigEditor
+                config={props.config.clone()}
+                on_change={props.on_config_change.clone()}
+                disabled={props.disabled}
+            />
+        </>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+struct ConfigEditorProps {
+    config: SearchConfig,
+    on_change: Callback<SearchConfig>,
+    disabled: bool,
+}
+
+/// The whole config as JSON, so it can be copied elsewhere or replaced by a saved one.
+#[function_component(ConfigEditor)]
+fn config_editor(props: &ConfigEditorProps) -> Html {
+    let error = use_state(|| None::<Error>);
+    let on_change = {
+        let error = error.clone();
+        let on_config_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let Some(textarea) = e.target_dyn_into::<HtmlTextAreaElement>() else {
+                return;
+            };
+            match SearchConfig::from_json(&textarea.value()) {
+                Ok(config) => {
+                    error.set(None);
+                    on_config_change.emit(config);
+                }
+                Err(err) => error.set(Some(err)),
+            }
+        })
+    };
+    html! {
+        <details class="config-editor">
+            <summary>{"Configuration (JSON)"}</summary>
+            <textarea
+                id="configInput"
+                rows="12"
+                value={props.config.to_json()}
+                onchange={on_change}
+                disabled={props.disabled}
+            />
+            {
+                if let Some(err) = &*error {
+                    html! { <div class="error-message">{err.to_string()}</div> }
+                } else {
+                    html! {}
+                }
+            }
+            <ul class="settings-hint">
+                <li>{"Copy the configuration to repeat the search later, or paste a saved one."}</li>
+                <li>{"Regions are in pixels of the main image, so they are meaningful only for images of the same size."}</li>
+            </ul>
+        </details>
+    }
+}
+
+fn main_image_help() -> Html {
+    html! {
+        <ul class="image-hint">
+            <li><strong>{"More images"}</strong>{" can be selected at once for a batch search. The results are ranked by the best match."}</li>
+        </ul>
+    }
+}
+
+fn mask_help(props: &SearchParamsProps) -> Html {
+    let on_remove = props.on_mask_image_remove.reform(|e: MouseEvent| {
+        // The button is inside the label, which would open the file dialog otherwise
+        e.prevent_default();
+    });
+    html! {
+        <>
+            <ul class="image-hint">
+                <li><strong>{"Size"}</strong>{" has to be the same as the size of the main image."}</li>
+                <li><strong>{"Dark opaque pixels"}</strong>{" are excluded, light or transparent pixels are searched."}</li>
+                <li>{"Matches overlapping an excluded pixel are discarded."}</li>
+            </ul>
+            {
+                if props.mask_image.is_some() {
+                    html! {
+                        <button class="edit-button" onclick={on_remove} disabled={props.disabled}>
+                            {"Remove mask"}
+                        </button>
+                    }
+                } else {
+                    html! {}
+                }
+            }
         </>
     }
 }
@@ -112,6 +603,7 @@ pub fn search_params(props: &SearchParamsProps) -> Html {
 fn image_search_help() -> Html {
     html! {
         <ul class="image-hint">
+            <li><strong>{"More images"}</strong>{" can be selected at once. All of them are searched in a single pass."}</li>
             <li><strong>{"Orientation"}</strong>{" has to be the same as in main image."}</li>
             <li><strong>{"Scale"}</strong>{" has to be the same as in main image."}</li>
             <li><strong>{"Compression artifacts and blur caused by scaling up"}</strong>{" can be handled by increasing the maximum difference."}</li>
@@ -1,8 +1,389 @@
+use crate::components::result_viewer::{MatchId, ResultViewer, Viewport, ViewportAction};
+use crate::config::{Metric, ThresholdUnit};
+use crate::download_file;
+use crate::error::Error;
+use crate::image::{ErrorMap, NEAREST_MISS_GRID, NearestMiss, Rect, SearchResults};
+use std::rc::Rc;
+use web_sys::{HtmlInputElement, HtmlSelectElement};
 use yew::prelude::*;
 
+// Colors distinguishing overlays of individual templates
+const TEMPLATE_COLORS: [&str; 8] = [
+    "#00ff00", "#ff00ff", "#00bfff", "#ff8c00", "#ffff00", "#ff1744", "#7c4dff", "#00e5ff",
+];
+
+pub fn template_color(template_index: usize) -> &'static str {
+    TEMPLATE_COLORS[template_index % TEMPLATE_COLORS.len()]
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SearchResultsViewProps {
+    pub main_image: String,
+    // One item per template
+    pub results: Rc<Vec<SearchResults>>,
+    // The search is still running, so the results may change
+    #[prop_or_default]
+    pub live: bool,
+    // Animations and multi-page images show the results of a single frame at once
+    #[prop_or_default]
+    pub frame_index: usize,
+    #[prop_or(1)]
+    pub frame_count: usize,
+    #[prop_or_default]
+    pub frames_with_matches: Vec<usize>,
+    // None while the frames are being searched
+    #[prop_or_default]
+    pub on_frame_change: Option<Callback<usize>>,
+    // None while the frames are being searched
+    #[prop_or_default]
+    pub on_inspect: Option<Callback<MatchId>>,
+    // Errors of all positions, one item per template when the search recorded them
+    #[prop_or_default]
+    pub error_landscapes: Vec<Rc<ErrorMap>>,
+    // Unit of the scores of the matches
+    #[prop_or_default]
+    pub unit: ThresholdUnit,
+    #[prop_or_default]
+    pub metric: Metric,
+}
+
+fn result_message(results: &[SearchResults], live: bool) -> String {
+    let match_count: usize = results.iter().map(|r| r.get_matches().len()).sum();
+    if live {
+        format!("Searching… {} matches so far", match_count)
+    } else if results.iter().any(|r| r.has_overflown()) {
+        format!("Found many matches, showing {} most relevant", match_count)
+    } else if match_count == 0 {
+        "No matches found".to_string()
+    } else {
+        format!("Found {} matches", match_count)
+    }
+}
+
+// Explains a template without matches by its best position and the errors of its parts
+fn nearest_miss_view(
+    search_results: &SearchResults,
+    miss: &NearestMiss,
+    on_show: &Callback<Rect>,
+    (metric, unit): (Metric, ThresholdUnit),
+) -> Html {
+    let rect = Rect {
+        x: miss.x,
+        y: miss.y,
+        width: search_results.get_template_width(),
+        height: search_results.get_template_height(),
+    };
+    let max = miss.region_mse.iter().copied().fold(0.0, f64::max);
+    // The parts are known only as an MSE, not as a deviation
+    let region_unit = unit.mse_unit();
+    html! {
+        <div class="nearest-miss">
+            <p>
+                {format!(
+                    "No match. The nearest miss is at ({}, {}) with {} {}; set the maximum difference to at least that to find it.",
+                    miss.x,
+                    miss.y,
+                    metric.score_label(unit),
+                    miss.format_score(unit),
+                )}
+                <button class="inspect-button" onclick={on_show.reform(move |_: MouseEvent| rect)}>{"Show"}</button>
+            </p>
+            <table class="region-errors" title={format!("{} of the parts of the template", metric.score_label(region_unit))}>
+                {
+                    miss.region_mse.chunks(NEAREST_MISS_GRID as usize).map(|row| html! {
+                        <tr>
+                            {
+                                row.iter().map(|&mse| {
+                                    // Parts with larger errors are darker
+                                    let shade = if max > 0.0 && !mse.is_nan() { mse / max } else { 0.0 };
+                                    html! {
+                                        <td style={format!("background-color: rgba(220, 53, 69, {:.2})", shade * 0.6)}>
+                                            {if mse.is_nan() { "-".to_string() } else { region_unit.format(mse) }}
+                                        </td>
+                                    }
+                                }).collect::<Html>()
+                            }
+                        </tr>
+                    }).collect::<Html>()
+                }
+            </table>
+            {
+                if let Some(cause) = miss.likely_cause() {
+                    html! { <p class="likely-cause">{format!("Likely cause: {}.", cause)}</p> }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+fn matches_list(
+    template_index: usize,
+    search_results: &SearchResults,
+    selected: Option<MatchId>,
+    on_select: &Callback<(MatchId, Rect)>,
+    on_inspect: Option<&Callback<MatchId>>,
+    on_show: &Callback<Rect>,
+    (metric, unit): (Metric, ThresholdUnit),
+) -> Html {
+    html! {
+        <div class="template-matches">
+            <h3>
+                <span class="template-color" style={format!("background-color: {}", template_color(template_index))} />
+                {format!("{} ({})", search_results.get_template_name(), search_results.get_matches().len())}
+            </h3>
+            {
+                if let Some(miss) = search_results.get_nearest_miss() {
+                    nearest_miss_view(search_results, miss, on_show, (metric, unit))
+                } else {
+                    html! {}
+                }
+            }
+            <ol class="matches-list">
+                {
+                    search_results.get_matches().iter().enumerate().map(|(i, m)| {
+                        let id = (template_index, i);
+                        let rect = Rect {
+                            x: m.x,
+                            y: m.y,
+                            width: search_results.get_template_width(),
+                            height: search_results.get_template_height(),
+                        };
+                        html! {
+                            <li
+                                class={classes!("match-item", (selected == Some(id)).then_some("selected"))}
+                                data-match-id={i.to_string()}
+                                title="Zoom to match"
+                                onclick={on_select.reform(move |_: MouseEvent| (id, rect))}
+                            >
+                                {format!("Match at ({}, {}) - {}: {}",
+                                    m.x,
+                                    m.y,
+                                    metric.score_label(unit),
+                                    search_results.format_score(m, unit)
+                                )}
+                                {
+                                    if let Some(on_inspect) = on_inspect {
+                                        html! {
+                                            <button
+                                                class="inspect-button"
+                                                onclick={on_inspect.reform(move |_: MouseEvent| id)}
+                                            >
+                                                {"Inspect"}
+                                            </button>
+                                        }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                            </li>
+                        }
+                    }).collect::<Html>()
+                }
+            </ol>
+        </div>
+    }
+}
+
+fn frame_scrubber(props: &SearchResultsViewProps) -> Html {
+    let on_input = props.on_frame_change.clone().map(|on_frame_change| {
+        Callback::from(move |e: InputEvent| {
+            if let Some(index) = e
+                .target_dyn_into::<HtmlInputElement>()
+                .and_then(|input| input.value().parse().ok())
+            {
+                on_frame_change.emit(index);
+            }
+        })
+    });
+    html! {
+        <div class="frame-scrubber">
+            <input
+                type="range"
+                id="frameInput"
+                min="0"
+                max={(props.frame_count - 1).to_string()}
+                value={props.frame_index.to_string()}
+                oninput={on_input}
+                disabled={props.on_frame_change.is_none()}
+            />
+            <span class="frame-position">{format!("Frame {} of {}", props.frame_index + 1, props.frame_count)}</span>
+            {
+                if let Some(on_frame_change) = &props.on_frame_change {
+                    html! {
+                        <div class="frames-with-matches">
+                            {
+                                if props.frames_with_matches.is_empty() {
+                                    html! { {"No frame has matches"} }
+                                } else {
+                                    html! { {"Frames with matches: "} }
+                                }
+                            }
+                            {
+                                props.frames_with_matches.iter().map(|&index| html! {
+                                    <button
+                                        class={classes!("frame-link", (index == props.frame_index).then_some("selected"))}
+                                        onclick={on_frame_change.reform(move |_: MouseEvent| index)}
+                                    >
+                                        {index + 1}
+                                    </button>
+                                }).collect::<Html>()
+                            }
+                        </div>
+                    }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+fn export_landscape(template_name: &str, landscape: &ErrorMap, raw: bool) -> Result<(), Error> {
+    if raw {
+        // The size is not stored in the raw file, so it is in the name
+        let file_name = format!(
+            "{}.errors.{}x{}.f32",
+            template_name,
+            landscape.get_width(),
+            landscape.get_height()
+        );
+        download_file(
+            &file_name,
+            &landscape.to_raw_f32(),
+            "application/octet-stream",
+        )
+    } else {
+        let file_name = format!("{}.errors.png", template_name);
+        download_file(&file_name, &landscape.to_gray16_png()?, "image/png")
+    }
+}
+
+fn landscape_controls(
+    props: &SearchResultsViewProps,
+    shown: &UseStateHandle<Option<usize>>,
+    export_error: &UseStateHandle<Option<Error>>,
+) -> Html {
+    let on_change = {
+        let shown = shown.clone();
+        Callback::from(move |e: Event| {
+            shown.set(
+                e.target_dyn_into::<HtmlSelectElement>()
+                    .and_then(|select| select.value().parse().ok()),
+            );
+        })
+    };
+    let on_export = |raw: bool| {
+        let shown = **shown;
+        let export_error = export_error.clone();
+        let results = props.results.clone();
+        let landscapes = props.error_landscapes.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some((search_results, landscape)) =
+                shown.and_then(|i| results.get(i).zip(landscapes.get(i)))
+            else {
+                return;
+            };
+            let result = export_landscape(search_results.get_template_name(), landscape, raw);
+            if let Err(err) = &result {
+                log::error!("{}", err);
+            }
+            export_error.set(result.err());
+        })
+    };
+    html! {
+        <div class="landscape-controls">
+            <label>
+                {"Error heatmap: "}
+                <select id="landscapeInput" onchange={on_change}>
+                    <option value="" selected={shown.is_none()}>{"None"}</option>
+                    {
+                        props.results.iter().take(props.error_landscapes.len()).enumerate().map(|(i, r)| html! {
+                            <option value={i.to_string()} selected={**shown == Some(i)}>{r.get_template_name()}</option>
+                        }).collect::<Html>()
+                    }
+                </select>
+            </label>
+            <button class="edit-button" onclick={on_export(false)} disabled={shown.is_none()}>
+                {"Export 16-bit PNG"}
+            </button>
+            <button class="edit-button" onclick={on_export(true)} disabled={shown.is_none()}>
+                {"Export raw floats"}
+            </button>
+            <span class="settings-hint">
+                {"Dark positions are similar to the template, the top-left corner of the template is at the position."}
+            </span>
+            {
+                if let Some(err) = &**export_error {
+                    html! { <div class="error-message">{err.to_string()}</div> }
+                } else {
+                    html! {}
+                }
+            }
+        </div>
+    }
+}
+
+#[function_component(SearchResultsView)]
+pub fn search_results_view(props: &SearchResultsViewProps) -> Html {
+    let viewport = use_reducer(Viewport::default);
+    let selected = use_state(|| None::<MatchId>);
+    // Template whose error landscape is drawn over the main image
+    let shown_landscape = use_state(|| None::<usize>);
+    let export_error = use_state(|| None::<Error>);
+
+    let on_select = {
+        let dispatcher = viewport.dispatcher();
+        let selected = selected.clone();
+        Callback::from(move |(id, rect): (MatchId, Rect)| {
+            selected.set(Some(id));
+            dispatcher.dispatch(ViewportAction::Show(rect));
+        })
+    };
+
+    let on_show = {
+        let dispatcher = viewport.dispatcher();
+        Callback::from(move |rect: Rect| dispatcher.dispatch(ViewportAction::Show(rect)))
+    };
+
+    html! {
+        <div class="result-container">
+            <h2>{"Search results"}</h2>
+            <div class="result-message">
+                <h3>{result_message(&props.results, props.live)}</h3>
+            </div>
+            {
+                if props.frame_count > 1 {
+                    frame_scrubber(props)
+                } else {
+                    html! {}
+                }
+            }
+            {
+                if props.error_landscapes.is_empty() {
+                    html! {}
+                } else {
+                    landscape_controls(props, &shown_landscape, &export_error)
+                }
+            }
+            <ResultViewer
+                image={props.main_image.clone()}
+                results={props.results.clone()}
+                viewport={viewport}
+                selected={*selected}
+                heatmap={shown_landscape.and_then(|i| props.error_landscapes.get(i).cloned())}
+            />
+            {
+                props.results.iter().enumerate().map(|(i, r)| matches_list(i, r, *selected, &on_select, props.on_inspect.as_ref(), &on_show, (props.metric, props.unit))).collect::<Html>()
+            }
+        </div>
+    }
+}
+
 #[derive(Properties, PartialEq)]
 pub struct SearchErrorProps {
-    pub message: AttrValue,
+    pub error: Error,
 }
 
 #[function_component(SearchError)]
@@ -11,8 +392,9 @@ pub fn search_error(props: &SearchErrorProps) -> Html {
         <div class="result-container">
             <h2>{"Error"}</h2>
             <div class="error-message">
-                <h3>{"An error occurred during processing"}</h3>
-                <p>{&props.message}</p>
+                <h3>{props.error.title()}</h3>
+                <p>{props.error.to_string()}</p>
+                <p class="error-hint">{props.error.hint()}</p>
             </div>
         </div>
     }
@@ -1,30 +1,150 @@
+use crate::UploadedImage;
+use crate::components::search_results::template_color;
+use crate::config::{Channels, ErrorLandscape, Metric, SearchConfig, ThresholdMode};
 use yew::prelude::*;
 
 #[derive(Properties, PartialEq)]
 pub struct SearchSummaryProps {
-    pub search_image: String,
-    pub max_mse: f64,
-    pub max_results: u16,
+    pub search_images: Vec<UploadedImage>,
+    // Settings of the displayed results
+    pub config: SearchConfig,
+    // Settings of the next searches, whose unit is an MSE once a picked threshold is pinned
+    pub next_config: SearchConfig,
+    pub has_mask_image: bool,
+    // Maximum differences picked by the automatic threshold, by template name
+    pub auto_thresholds: Vec<(String, f64)>,
+    // Maximum differences of single templates in the displayed results, by template name
+    pub pinned_thresholds: Vec<(String, f64)>,
+    // Maximum differences of single templates for the next searches
+    pub next_pinned_thresholds: Vec<(String, f64)>,
+    // Sets the maximum difference of a template for the next searches
+    pub on_pin_threshold: Callback<(String, f64)>,
     pub on_new_search: Callback<()>,
 }
 
 #[function_component(SearchSummary)]
 pub fn search_summary(props: &SearchSummaryProps) -> Html {
+    let config = &props.config;
     html! {
         <div class="search-summary">
             <h2>{"Search summary"}</h2>
             <div class="search-info">
                 <div class="search-image-preview">
-                    <h3>{"Searched subimage"}</h3>
-                    <img
-                        src={props.search_image.clone()}
-                        alt="Subimage that was searched"
-                    />
+                    <h3>{if props.search_images.len() > 1 {"Searched subimages"} else {"Searched subimage"}}</h3>
+                    {
+                        props.search_images.iter().enumerate().map(|(i, image)| html! {
+                            <img
+                                src={image.url.clone()}
+                                alt="Subimage that was searched"
+                                title={image.name.clone()}
+                                style={format!("border-color: {}", template_color(i))}
+                            />
+                        }).collect::<Html>()
+                    }
+                    {
+                        // The cropped template comes after the uploaded ones
+                        if let Some(region) = &config.template_region {
+                            html! {
+                                <p style={format!("border-left: 4px solid {}; padding-left: 5px", template_color(props.search_images.len()))}>
+                                    {format!("Region {} of the main image", region)}
+                                    {if config.exclude_template_region {", other occurrences only"} else {""}}
+                                </p>
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
                 </div>
                 <div class="settings-summary">
                     <h3>{"Search Settings"}</h3>
-                    <span class="setting">{"Maximum difference: "}<strong>{format!("{:.1}%", props.max_mse * 100.0)}</strong></span>
-                    <span class="setting">{"Maximum results: "}<strong>{props.max_results}</strong></span>
+                    <span class="setting">{"Maximum difference: "}<strong>{
+                        match config.threshold_mode {
+                            ThresholdMode::Fixed if config.metric == Metric::MatchingPixels => {
+                                format!("{} of the pixels", config.threshold_unit.format(config.max_mse))
+                            }
+                            ThresholdMode::Fixed => config.threshold_unit.format(config.max_mse),
+                            ThresholdMode::Automatic => "automatic".to_string(),
+                            ThresholdMode::BestK => format!("none, the {} best positions", config.max_results),
+                        }
+                    }</strong></span>
+                    {
+                        props.pinned_thresholds.iter().map(|(name, threshold)| html! {
+                            <span class="setting">
+                                {format!("Pinned for {}: ", name)}
+                                <strong>{config.threshold_unit.mse_unit().format(*threshold)}</strong>
+                            </span>
+                        }).collect::<Html>()
+                    }
+                    {
+                        props.auto_thresholds.iter().map(|(name, threshold)| {
+                            let threshold = *threshold;
+                            let pinned = props
+                                .next_pinned_thresholds
+                                .contains(&(name.clone(), threshold));
+                            let pin = (name.clone(), threshold);
+                            html! {
+                                <span class="setting auto-threshold">
+                                    {format!("Picked for {}: ", name)}
+                                    <strong>{config.threshold_unit.mse_unit().format(threshold)}</strong>
+                                    <button
+                                        class="inspect-button"
+                                        title="Use this maximum difference for the next searches"
+                                        onclick={props.on_pin_threshold.reform(move |_: MouseEvent| pin.clone())}
+                                        disabled={pinned}
+                                    >
+                                        {if pinned { "Pinned" } else { "Pin" }}
+                                    </button>
+                                </span>
+                            }
+                        }).collect::<Html>()
+                    }
+                    <span class="setting">{"Score: "}<strong>{
+                        match config.metric {
+                            Metric::Mse => "mean squared error".to_string(),
+                            Metric::MatchingPixels => format!(
+                                "matching pixels, within {:.2}% MSE",
+                                config.pixel_tolerance * 100.0
+                            ),
+                            Metric::NormalizedChannels => "normalized brightness and contrast of each channel".to_string(),
+                            Metric::NormalizedLuminance => "normalized brightness and contrast of luminance".to_string(),
+                        }
+                    }</strong></span>
+                    <span class="setting">{"Maximum results: "}<strong>{config.max_results}</strong></span>
+                    <span class="setting">{"Compared channels: "}<strong>{
+                        match config.channels {
+                            Channels::Rgba => "color and transparency",
+                            Channels::Rgb => "color only",
+                        }
+                    }</strong></span>
+                    <span class="setting">{"Orientation and color profiles: "}<strong>{
+                        if config.apply_image_metadata { "applied" } else { "ignored" }
+                    }</strong></span>
+                    <span class="setting">{"Error heatmap: "}<strong>{
+                        match config.error_landscape {
+                            ErrorLandscape::Off => "off",
+                            ErrorLandscape::Capped => "capped at the maximum difference",
+                            ErrorLandscape::Exact => "exact",
+                        }
+                    }</strong></span>
+                    <span class="setting">{"Search regions: "}<strong>{
+                        if config.regions.is_empty() {
+                            "whole image".to_string()
+                        } else {
+                            config.regions.len().to_string()
+                        }
+                    }</strong></span>
+                    <span class="setting">{"Excluded areas: "}<strong>{
+                        match (props.has_mask_image, config.excluded_regions.len()) {
+                            (false, 0) => "none".to_string(),
+                            (true, 0) => "mask image".to_string(),
+                            (false, n) => n.to_string(),
+                            (true, n) => format!("mask image + {}", n),
+                        }
+                    }</strong></span>
+                    <details class="config-editor">
+                        <summary>{"Configuration (JSON)"}</summary>
+                        <textarea rows="12" readonly={true} value={config.to_json()} />
+                    </details>
                 </div>
                 <button class="edit-button" onclick={props.on_new_search.reform(|_| ())}>{"New Search"}</button>
             </div>
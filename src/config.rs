@@ -0,0 +1,507 @@
+use crate::error::Error;
+use crate::image::Rect;
+use serde::{Deserialize, Serialize};
+
+/// How the difference between the template and a position of the main image is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Mean squared error of the compared subpixels
+    #[default]
+    Mse,
+    /**
+     * Fraction of the template pixels whose MSE exceeds pixel_tolerance, so max_mse is the largest fraction
+     * of differing pixels. A cursor or a tooltip over a small part of the template doesn't ruin the match.
+     */
+    MatchingPixels,
+    /**
+     * MSE of the template and the window, each normalized to zero mean and unit variance in every channel,
+     * so brightness and contrast don't matter, e.g., for dark and light themes. It is (1 - correlation) / 2.
+     */
+    NormalizedChannels,
+    /// Like NormalizedChannels, but only the luminance is compared, so also the tint doesn't matter
+    NormalizedLuminance,
+}
+
+impl Metric {
+    /// Short name of the scores of matches, e.g., for column headings
+    pub fn score_label(self, unit: ThresholdUnit) -> &'static str {
+        match self {
+            Metric::Mse => unit.label(),
+            Metric::MatchingPixels => "Differing pixels",
+            Metric::NormalizedChannels | Metric::NormalizedLuminance => "Normalized error",
+        }
+    }
+}
+
+/// Subpixels taken into account when comparing pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channels {
+    #[default]
+    Rgba,
+    /// Ignores transparency, e.g., for templates saved with a different alpha channel than the screenshot
+    Rgb,
+}
+
+impl Channels {
+    pub fn count(self) -> u32 {
+        match self {
+            Channels::Rgba => 4,
+            Channels::Rgb => 3,
+        }
+    }
+}
+
+/// How the maximum difference of matches is decided.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdMode {
+    /// Matches have to be within max_mse
+    #[default]
+    Fixed,
+    /// Picked for each template and main image from the errors of the best positions, ignoring max_mse
+    Automatic,
+    /// The max_results best positions, whatever their errors
+    BestK,
+}
+
+// A difference of one level of an 8-bit subpixel as a fraction of the full scale, as in the search engine
+const LEVELS_PER_FULL_SCALE: f64 = 256.0;
+// Peak value of PSNR, by its usual definition for 8-bit images
+const PSNR_PEAK: f64 = 255.0;
+
+/**
+ * Unit in which the maximum difference is entered and the scores are reported.
+ * The threshold is always stored as max_mse, the units convert it back and forth.
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdUnit {
+    /// Mean squared error in percent of the largest possible error
+    #[default]
+    Mse,
+    /// Peak signal-to-noise ratio in dB, higher values are stricter
+    Psnr,
+    /// Root mean squared error in 8-bit levels (0-255)
+    Rmse,
+    /// No subpixel may differ by more than this many 8-bit levels, max_mse is the square of the deviation
+    MaxDeviation,
+}
+
+impl ThresholdUnit {
+    /// The value in this unit of an MSE, or of a squared deviation for MaxDeviation
+    pub fn from_mse(self, mse: f64) -> f64 {
+        match self {
+            ThresholdUnit::Mse => mse * 100.0,
+            ThresholdUnit::Psnr => {
+                10.0 * (PSNR_PEAK * PSNR_PEAK / (mse * LEVELS_PER_FULL_SCALE.powi(2))).log10()
+            }
+            ThresholdUnit::Rmse | ThresholdUnit::MaxDeviation => mse.sqrt() * LEVELS_PER_FULL_SCALE,
+        }
+    }
+
+    /// Inverse of from_mse
+    pub fn to_mse(self, value: f64) -> f64 {
+        match self {
+            ThresholdUnit::Mse => value / 100.0,
+            ThresholdUnit::Psnr => {
+                PSNR_PEAK * PSNR_PEAK / 10f64.powf(value / 10.0) / LEVELS_PER_FULL_SCALE.powi(2)
+            }
+            ThresholdUnit::Rmse | ThresholdUnit::MaxDeviation => {
+                (value / LEVELS_PER_FULL_SCALE).powi(2)
+            }
+        }
+    }
+
+    /// Unit for values known only as an MSE, which a maximum deviation cannot express
+    pub fn mse_unit(self) -> ThresholdUnit {
+        match self {
+            ThresholdUnit::MaxDeviation => ThresholdUnit::Mse,
+            unit => unit,
+        }
+    }
+
+    /// Short name of the unit, e.g., for column headings
+    pub fn label(self) -> &'static str {
+        match self {
+            ThresholdUnit::Mse => "MSE",
+            ThresholdUnit::Psnr => "PSNR",
+            ThresholdUnit::Rmse => "RMSE",
+            ThresholdUnit::MaxDeviation => "Max. deviation",
+        }
+    }
+
+    /// The value of an MSE (or squared deviation) in this unit with its symbol, e.g., "0.1234%" or "38.2 dB"
+    pub fn format(self, mse: f64) -> String {
+        let value = self.from_mse(mse);
+        match self {
+            ThresholdUnit::Mse => format!("{:.4}%", value),
+            ThresholdUnit::Psnr if value.is_infinite() => "∞ dB".to_string(),
+            ThresholdUnit::Psnr => format!("{:.2} dB", value),
+            ThresholdUnit::Rmse | ThresholdUnit::MaxDeviation => format!("{:.2} levels", value),
+        }
+    }
+}
+
+/// Errors recorded for every candidate position, e.g., for a heatmap showing how distinctive a template is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorLandscape {
+    #[default]
+    Off,
+    /// Errors above max_mse are recorded as max_mse, so the positions can still be rejected early
+    Capped,
+    /// Every position is compared completely, which makes the search slower
+    Exact,
+}
+
+/**
+ * Everything that affects the results of a search, except for the images themselves.
+ * It can be serialized (e.g., to JSON), so a search can be saved, shared and replayed exactly.
+ * Missing fields get default values when deserializing, call validate() before using a deserialized config.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    // Maximum mean squared error as a fraction of the maximum possible error, between 0.0 and 1.0
+    pub max_mse: f64,
+    pub threshold_mode: ThresholdMode,
+    pub threshold_unit: ThresholdUnit,
+    // Maximum number of results per template and main image
+    pub max_results: u16,
+    pub metric: Metric,
+    // Largest MSE of a single pixel that still counts as matching, between 0.0 and 1.0, only for Metric::MatchingPixels
+    pub pixel_tolerance: f64,
+    pub channels: Channels,
+    // Regions of interest for top-left corners of matches, empty means whole image
+    pub regions: Vec<Rect>,
+    // Areas of the main image that matches must not overlap
+    pub excluded_regions: Vec<Rect>,
+    // Part of the (first) main image searched as an additional template, e.g., to find other occurrences of a button
+    pub template_region: Option<Rect>,
+    // Discards matches overlapping template_region, so only the other occurrences are found
+    pub exclude_template_region: bool,
+    // Rotates images by their EXIF orientation and converts embedded ICC profiles to sRGB when decoding
+    pub apply_image_metadata: bool,
+    pub error_landscape: ErrorLandscape,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            max_mse: 0.01,
+            threshold_mode: ThresholdMode::default(),
+            threshold_unit: ThresholdUnit::default(),
+            max_results: 10,
+            metric: Metric::default(),
+            pixel_tolerance: 0.001,
+            channels: Channels::default(),
+            regions: Vec::new(),
+            excluded_regions: Vec::new(),
+            template_region: None,
+            exclude_template_region: true,
+            apply_image_metadata: true,
+            error_landscape: ErrorLandscape::default(),
+        }
+    }
+}
+
+impl SearchConfig {
+    pub fn builder() -> SearchConfigBuilder {
+        SearchConfigBuilder::default()
+    }
+
+    /// Builder starting from this config, e.g., for overriding a loaded config
+    pub fn into_builder(self) -> SearchConfigBuilder {
+        SearchConfigBuilder { config: self }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if !(0.0..=1.0).contains(&self.max_mse) {
+            return Err(Error::invalid_parameter(
+                "maximum difference",
+                format!("{}% is not between 0% and 100%", self.max_mse * 100.0),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.pixel_tolerance) {
+            return Err(Error::invalid_parameter(
+                "pixel tolerance",
+                format!(
+                    "{}% is not between 0% and 100%",
+                    self.pixel_tolerance * 100.0
+                ),
+            ));
+        }
+        if self.metric != Metric::Mse && self.threshold_unit != ThresholdUnit::Mse {
+            return Err(Error::invalid_parameter(
+                "threshold unit",
+                "only the mean squared error can be given in other units than percent",
+            ));
+        }
+        if self.max_results == 0 {
+            return Err(Error::invalid_parameter(
+                "maximum number of results",
+                "at least one result has to be allowed",
+            ));
+        }
+        for (name, regions) in [
+            ("search region", self.regions.as_slice()),
+            ("excluded region", self.excluded_regions.as_slice()),
+            ("template region", self.template_region.as_slice()),
+        ] {
+            if let Some(empty) = regions.iter().find(|r| r.width == 0 || r.height == 0) {
+                return Err(Error::invalid_parameter(
+                    name,
+                    format!("region at ({}, {}) is empty", empty.x, empty.y),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Areas discarding overlapping matches in a frame of a main image. The template region is
+    /// cropped from the first frame of the first main image, so it is only excluded there.
+    pub fn excluded_regions_in(&self, input_index: usize, frame_index: usize) -> Vec<Rect> {
+        let template_region = self
+            .template_region
+            .filter(|_| self.exclude_template_region && input_index == 0 && frame_index == 0);
+        self.excluded_regions
+            .iter()
+            .copied()
+            .chain(template_region)
+            .collect()
+    }
+
+    /**
+     * The config of a template with its own maximum difference or result cap, this config supplies the rest.
+     * A maximum difference of the template is always fixed, whatever the threshold mode.
+     */
+    pub fn for_template(&self, max_mse: Option<f64>, max_results: Option<u16>) -> SearchConfig {
+        let mut config = self.clone();
+        if let Some(max_mse) = max_mse {
+            config.max_mse = max_mse;
+            config.threshold_mode = ThresholdMode::Fixed;
+        }
+        if let Some(max_results) = max_results {
+            config.max_results = max_results;
+        }
+        config
+    }
+
+    /// This config with the regions drawn on the images of `other`, e.g., when applying a preset
+    pub fn with_regions_of(self, other: &SearchConfig) -> SearchConfig {
+        SearchConfig {
+            regions: other.regions.clone(),
+            excluded_regions: other.excluded_regions.clone(),
+            template_region: other.template_region,
+            ..self
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Plain data without maps with non-string keys, so serialization cannot fail
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Result<SearchConfig, Error> {
+        let config: SearchConfig = serde_json::from_str(json)
+            .map_err(|e| Error::invalid_parameter("configuration", e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Builds a validated SearchConfig, starting from the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct SearchConfigBuilder {
+    config: SearchConfig,
+}
+
+impl SearchConfigBuilder {
+    pub fn max_mse(mut self, max_mse: f64) -> Self {
+        self.config.max_mse = max_mse;
+        self
+    }
+    pub fn threshold_mode(mut self, threshold_mode: ThresholdMode) -> Self {
+        self.config.threshold_mode = threshold_mode;
+        self
+    }
+    pub fn threshold_unit(mut self, threshold_unit: ThresholdUnit) -> Self {
+        self.config.threshold_unit = threshold_unit;
+        self
+    }
+    pub fn max_results(mut self, max_results: u16) -> Self {
+        self.config.max_results = max_results;
+        self
+    }
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.config.metric = metric;
+        self
+    }
+    pub fn pixel_tolerance(mut self, pixel_tolerance: f64) -> Self {
+        self.config.pixel_tolerance = pixel_tolerance;
+        self
+    }
+    pub fn channels(mut self, channels: Channels) -> Self {
+        self.config.channels = channels;
+        self
+    }
+    pub fn regions(mut self, regions: Vec<Rect>) -> Self {
+        self.config.regions = regions;
+        self
+    }
+    pub fn excluded_regions(mut self, excluded_regions: Vec<Rect>) -> Self {
+        self.config.excluded_regions = excluded_regions;
+        self
+    }
+    pub fn template_region(mut self, template_region: Option<Rect>) -> Self {
+        self.config.template_region = template_region;
+        self
+    }
+    pub fn exclude_template_region(mut self, exclude_template_region: bool) -> Self {
+        self.config.exclude_template_region = exclude_template_region;
+        self
+    }
+    pub fn apply_image_metadata(mut self, apply_image_metadata: bool) -> Self {
+        self.config.apply_image_metadata = apply_image_metadata;
+        self
+    }
+    pub fn error_landscape(mut self, error_landscape: ErrorLandscape) -> Self {
+        self.config.error_landscape = error_landscape;
+        self
+    }
+    pub fn build(self) -> Result<SearchConfig, Error> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_validation() {
+        assert_eq!(SearchConfig::builder().build(), Ok(SearchConfig::default()));
+        assert!(SearchConfig::builder().max_mse(1.5).build().is_err());
+        assert!(SearchConfig::builder().max_mse(f64::NAN).build().is_err());
+        assert!(SearchConfig::builder().max_results(0).build().is_err());
+        assert!(
+            SearchConfig::builder()
+                .pixel_tolerance(-0.1)
+                .build()
+                .is_err()
+        );
+        assert!(
+            SearchConfig::builder()
+                .metric(Metric::MatchingPixels)
+                .threshold_unit(ThresholdUnit::Psnr)
+                .build()
+                .is_err()
+        );
+        let empty = Rect {
+            x: 1,
+            y: 2,
+            width: 0,
+            height: 3,
+        };
+        assert!(
+            SearchConfig::builder()
+                .regions(vec![empty])
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_for_template() {
+        let config = SearchConfig {
+            threshold_mode: ThresholdMode::Automatic,
+            ..SearchConfig::default()
+        };
+        assert_eq!(config.for_template(None, None), config);
+        let template_config = config.for_template(Some(0.02), Some(3));
+        // A maximum difference of the template is never picked automatically
+        assert_eq!(template_config.threshold_mode, ThresholdMode::Fixed);
+        assert_eq!(template_config.max_mse, 0.02);
+        assert_eq!(template_config.max_results, 3);
+        assert_eq!(
+            config.for_template(None, Some(3)).threshold_mode,
+            ThresholdMode::Automatic
+        );
+    }
+
+    #[test]
+    fn test_excluded_regions_in() {
+        let rect = |x| Rect {
+            x,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        let config = SearchConfig {
+            excluded_regions: vec![rect(0)],
+            template_region: Some(rect(5)),
+            ..SearchConfig::default()
+        };
+        assert_eq!(config.excluded_regions_in(0, 0), vec![rect(0), rect(5)]);
+        // Other main images and later frames may contain the template at the same place
+        assert_eq!(config.excluded_regions_in(1, 0), vec![rect(0)]);
+        assert_eq!(config.excluded_regions_in(0, 1), vec![rect(0)]);
+        let config = SearchConfig {
+            exclude_template_region: false,
+            ..config
+        };
+        assert_eq!(config.excluded_regions_in(0, 0), vec![rect(0)]);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let config = SearchConfig::builder()
+            .max_mse(0.05)
+            .max_results(3)
+            .threshold_mode(ThresholdMode::Automatic)
+            .threshold_unit(ThresholdUnit::Psnr)
+            .channels(Channels::Rgb)
+            .excluded_regions(vec![Rect {
+                x: 1,
+                y: 2,
+                width: 3,
+                height: 4,
+            }])
+            .build()
+            .unwrap();
+        assert_eq!(SearchConfig::from_json(&config.to_json()), Ok(config));
+        // Missing fields get the defaults
+        let partial = SearchConfig::from_json(r#"{"max_results": 5}"#).unwrap();
+        assert_eq!(partial.max_results, 5);
+        assert_eq!(partial.max_mse, SearchConfig::default().max_mse);
+        assert!(SearchConfig::from_json(r#"{"max_mse": 2}"#).is_err());
+        assert!(SearchConfig::from_json(r#"{"channels": "cmyk"}"#).is_err());
+    }
+
+    #[test]
+    fn test_threshold_units() {
+        for unit in [
+            ThresholdUnit::Mse,
+            ThresholdUnit::Psnr,
+            ThresholdUnit::Rmse,
+            ThresholdUnit::MaxDeviation,
+        ] {
+            let mse = 0.0123;
+            assert!(
+                (unit.to_mse(unit.from_mse(mse)) - mse).abs() < 1e-12,
+                "{:?}",
+                unit
+            );
+        }
+        // A difference of one level in every subpixel
+        let one_level = 1.0 / 65536.0;
+        assert_eq!(ThresholdUnit::Rmse.from_mse(one_level), 1.0);
+        assert_eq!(ThresholdUnit::MaxDeviation.to_mse(2.0), 4.0 * one_level);
+        assert!((ThresholdUnit::Psnr.from_mse(one_level) - 48.13).abs() < 0.01);
+        assert_eq!(ThresholdUnit::Psnr.format(0.0), "∞ dB");
+        assert_eq!(ThresholdUnit::Mse.format(0.01), "1.0000%");
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// Everything that can go wrong between uploading the images and displaying the results.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The image couldn't be read or decoded.
+    Decode {
+        image: String,
+        reason: String,
+    },
+    TemplateLargerThanImage {
+        template: String,
+        template_width: u32,
+        template_height: u32,
+        main_width: u32,
+        main_height: u32,
+    },
+    InvalidParameter {
+        name: String,
+        reason: String,
+    },
+    /// The user has stopped the search.
+    Cancelled,
+    /// The input is too large to be processed.
+    ResourceLimit {
+        resource: String,
+        limit: u64,
+        actual: u64,
+    },
+    /// The browser failed to provide something the application relies on (canvas, DOM element, file reader…).
+    Browser(String),
+}
+
+impl Error {
+    pub fn invalid_parameter(name: &str, reason: impl Into<String>) -> Error {
+        Error::InvalidParameter {
+            name: name.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Short headline for the UI
+    pub fn title(&self) -> &'static str {
+        match self {
+            Error::Decode { .. } => "The image cannot be loaded",
+            Error::TemplateLargerThanImage { .. } => {
+                "The searched image is larger than the main image"
+            }
+            Error::InvalidParameter { .. } => "Invalid search settings",
+            Error::Cancelled => "The search was cancelled",
+            Error::ResourceLimit { .. } => "The image is too large",
+            Error::Browser(_) => "The browser failed to process the images",
+        }
+    }
+
+    /// What the user can do about it
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Error::Decode { .. } => {
+                "Check that the file is a valid image in a supported format, e.g., by opening it in an image viewer."
+            }
+            Error::TemplateLargerThanImage { .. } => {
+                "Swap the images, or crop the searched image. The searched image has to fit in the main image."
+            }
+            Error::InvalidParameter { .. } => "Fix the settings and search again.",
+            Error::Cancelled => "Start a new search when ready.",
+            Error::ResourceLimit { .. } => {
+                "Scale down or crop the image, or restrict the search to smaller regions."
+            }
+            Error::Browser(_) => "Reload the page and try again, or try another browser.",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Decode { image, reason } => write!(f, "Cannot decode {}: {}", image, reason),
+            Error::TemplateLargerThanImage {
+                template,
+                template_width,
+                template_height,
+                main_width,
+                main_height,
+            } => write!(
+                f,
+                "Search image {} ({}x{}) doesn't fit in the main image ({}x{})",
+                template, template_width, template_height, main_width, main_height
+            ),
+            Error::InvalidParameter { name, reason } => write!(f, "Invalid {}: {}", name, reason),
+            Error::Cancelled => write!(f, "The search was cancelled"),
+            Error::ResourceLimit {
+                resource,
+                limit,
+                actual,
+            } => write!(
+                f,
+                "Too many {}: {} exceeds the limit of {}",
+                resource, actual, limit
+            ),
+            Error::Browser(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
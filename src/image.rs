@@ -1,23 +1,421 @@
-use gloo::utils::{document, window};
-use wasm_bindgen::JsCast;
+use crate::config::{Channels, ErrorLandscape, Metric, SearchConfig, ThresholdMode, ThresholdUnit};
+use crate::error::Error;
+use ::image::ImageDecoder;
+use ::image::metadata::Orientation;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::ops::Range;
+use std::rc::Rc;
 
+#[cfg(target_arch = "wasm32")]
 async fn yield_now() {
+    use gloo::utils::window;
+
     // We will create a Promise that resolves after a short delay to allow the browser to update the UI
     let delay_promise = js_sys::Promise::new(&mut |resolve, _| {
-        window()
+        if window()
             .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, 0)
-            .unwrap();
+            .is_err()
+        {
+            // Not yielding is better than never resuming
+            let _ = resolve.call0(&wasm_bindgen::JsValue::NULL);
+        }
     });
-    // We need to convert the Promise to Rust Future and await it
-    wasm_bindgen_futures::JsFuture::from(delay_promise)
-        .await
-        .unwrap();
+    // We need to convert the Promise to Rust Future and await it. It never rejects.
+    let _ = wasm_bindgen_futures::JsFuture::from(delay_promise).await;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn yield_now() {
+    // Native builds (CLI) have no UI to update
 }
 
-pub struct ImageData {
+/// Wall clock time in milliseconds, for measuring durations only
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+/// Wall clock time in milliseconds, for measuring durations only
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+}
+
+/// Axis-aligned rectangle in pixel coordinates of the main image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
     pub width: u32,
     pub height: u32,
-    pixels: Vec<u8>, // RGBA pixel data
+}
+
+impl Rect {
+    pub fn contains_row(&self, y: u32) -> bool {
+        y >= self.y && y - self.y < self.height
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        self.contains_row(y) && x >= self.x && x - self.x < self.width
+    }
+}
+
+impl std::fmt::Display for Rect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {}) {}×{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/**
+ * Ranges of x coordinates to be scanned in row y. Regions bound the top-left corner of candidate positions,
+ * not the whole match. No regions means the whole row.
+ * The ranges are sorted and do not overlap, so no position is checked twice.
+ */
+fn candidate_ranges(regions: &[Rect], y: u32, x_end: u32) -> Vec<Range<u32>> {
+    if regions.is_empty() {
+        return std::iter::once(0..x_end).collect();
+    }
+    let mut ranges: Vec<Range<u32>> = regions
+        .iter()
+        .filter(|r| r.contains_row(y))
+        .map(|r| r.x.min(x_end)..r.x.saturating_add(r.width).min(x_end))
+        .filter(|r| !r.is_empty())
+        .collect();
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<u32>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/**
+ * Pixels of the main image excluded from the search (timestamps, ads, dynamic content etc.).
+ * Matches overlapping any excluded pixel are discarded.
+ */
+pub struct Mask {
+    width: u32,
+    // Prefix sums of excluded pixels, width + 1 items per row, so a row segment can be checked in O(1)
+    excluded_prefix: Vec<u32>,
+}
+
+impl Mask {
+    pub fn from_fn<F>(width: u32, height: u32, is_excluded: F) -> Mask
+    where
+        F: Fn(u32, u32) -> bool,
+    {
+        let mut excluded_prefix = Vec::with_capacity(((width + 1) * height) as usize);
+        for y in 0..height {
+            let mut count = 0;
+            excluded_prefix.push(count);
+            for x in 0..width {
+                if is_excluded(x, y) {
+                    count += 1;
+                }
+                excluded_prefix.push(count);
+            }
+        }
+        Mask {
+            width,
+            excluded_prefix,
+        }
+    }
+
+    /**
+     * Combines an uploaded mask image and drawn rectangles.
+     * In the mask image, dark opaque pixels are excluded, while light or transparent pixels are kept.
+     */
+    pub fn from_parts(
+        width: u32,
+        height: u32,
+        mask_image: Option<&ImageData>,
+        rects: &[Rect],
+    ) -> Result<Mask, Error> {
+        if let Some(mask_image) = mask_image
+            && (mask_image.width != width || mask_image.height != height)
+        {
+            return Err(Error::invalid_parameter(
+                "mask image",
+                format!(
+                    "its size {}x{} differs from main image size {}x{}",
+                    mask_image.width, mask_image.height, width, height
+                ),
+            ));
+        }
+        Ok(Mask::from_fn(width, height, |x, y| {
+            rects.iter().any(|r| r.contains(x, y))
+                || mask_image.is_some_and(|image| {
+                    let rgba = image.get_pixels(x, y, 1);
+                    let brightness = rgba[0] as u32 + rgba[1] as u32 + rgba[2] as u32;
+                    rgba[3] >= 128 && brightness < 3 * 128
+                })
+        }))
+    }
+
+    fn overlaps_row(&self, x: u32, y: u32, count: u32) -> bool {
+        let row_start = (y * (self.width + 1)) as usize;
+        self.excluded_prefix[row_start + (x + count) as usize]
+            > self.excluded_prefix[row_start + x as usize]
+    }
+}
+
+/// Precision of the channels of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Depth {
+    // Ordered by precision, so the most precise one of more images is the maximum
+    U8,
+    U16,
+    F32,
+}
+
+impl Depth {
+    /**
+     * TotalSquaredError of a full-scale difference of a single subpixel, used for normalizing MSE to 0..1.
+     * It is (2^bits)^2 rather than (2^bits - 1)^2, so 8-bit MSE values stay the same as they always were.
+     * Floats are nominally in 0..1 and their errors are stored as fixed point numbers with 32 fractional bits.
+     */
+    pub fn full_scale_squared_error(self) -> f64 {
+        match self {
+            Depth::U8 => 65536.0,
+            Depth::U16 | Depth::F32 => 4294967296.0,
+        }
+    }
+}
+
+/// Type of a single channel value, with an accumulator wide enough for summing its squared errors.
+pub trait Subpixel: Copy + PartialEq + std::fmt::Debug + 'static {
+    type Accumulator: Copy + std::iter::Sum;
+    const DEPTH: Depth;
+    fn squared_error(a: Self, b: Self) -> Self::Accumulator;
+    /// Converts an accumulated error to the integer representation shared by all depths
+    fn to_total_squared_error(accumulator: Self::Accumulator) -> TotalSquaredError;
+    /// RGBA channels of the image, converted to this type
+    fn from_image(image: &::image::DynamicImage) -> Vec<Self>;
+    /// The value in the scale of the type, e.g., for normalizing windows
+    fn to_f64(self) -> f64;
+}
+
+impl Subpixel for u8 {
+    type Accumulator = TotalSquaredError;
+    const DEPTH: Depth = Depth::U8;
+    fn squared_error(a: u8, b: u8) -> TotalSquaredError {
+        subpixel_squared_error(a, b)
+    }
+    fn to_total_squared_error(accumulator: TotalSquaredError) -> TotalSquaredError {
+        accumulator
+    }
+    fn from_image(image: &::image::DynamicImage) -> Vec<u8> {
+        image.to_rgba8().into_raw()
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Subpixel for u16 {
+    // The square of a difference takes 32 bits, the sum of a row can take up to 32 + 2 + 28 bits
+    type Accumulator = TotalSquaredError;
+    const DEPTH: Depth = Depth::U16;
+    fn squared_error(a: u16, b: u16) -> TotalSquaredError {
+        (a as i64 - b as i64).pow(2) as TotalSquaredError
+    }
+    fn to_total_squared_error(accumulator: TotalSquaredError) -> TotalSquaredError {
+        accumulator
+    }
+    fn from_image(image: &::image::DynamicImage) -> Vec<u16> {
+        image.to_rgba16().into_raw()
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Subpixel for f32 {
+    // Squares of HDR values can be large and tiny at once, so the row is summed in floating point
+    type Accumulator = TotalSquaredErrorFloat;
+    const DEPTH: Depth = Depth::F32;
+    fn squared_error(a: f32, b: f32) -> TotalSquaredErrorFloat {
+        (a as TotalSquaredErrorFloat - b as TotalSquaredErrorFloat).powi(2)
+    }
+    fn to_total_squared_error(accumulator: TotalSquaredErrorFloat) -> TotalSquaredError {
+        if accumulator.is_nan() {
+            // NaN pixels cannot match anything
+            TotalSquaredError::MAX
+        } else {
+            // The cast saturates, so errors of HDR values far above 1.0 cannot overflow
+            (accumulator * Depth::F32.full_scale_squared_error()) as TotalSquaredError
+        }
+    }
+    fn from_image(image: &::image::DynamicImage) -> Vec<f32> {
+        image.to_rgba32f().into_raw()
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+// Variance relative to the mean square below which a window is flat and isn't scaled up, just centered
+const FLAT_VARIANCE: f64 = 1e-9;
+
+// Values of a pixel that are normalized: the luminance, or the RGBA channels of which the compared ones are used
+fn normalized_values<P: Subpixel>(pixel: &[P], luminance: bool) -> [f64; 4] {
+    if luminance {
+        // Rec. 709 weights, the alpha channel is ignored
+        [
+            0.2126 * pixel[0].to_f64() + 0.7152 * pixel[1].to_f64() + 0.0722 * pixel[2].to_f64(),
+            0.0,
+            0.0,
+            0.0,
+        ]
+    } else {
+        [
+            pixel[0].to_f64(),
+            pixel[1].to_f64(),
+            pixel[2].to_f64(),
+            pixel[3].to_f64(),
+        ]
+    }
+}
+
+/**
+ * Sums and sums of squares of the normalized values of all the rectangles starting at the origin,
+ * so the mean and the variance of any window are known in constant time.
+ */
+struct IntegralImage {
+    width: usize, // One more than the image, the first row and column are zeros
+    luminance: bool,
+    values_per_pixel: usize,
+    sums: Vec<f64>,
+    squares: Vec<f64>,
+}
+
+impl IntegralImage {
+    fn new<P: Subpixel>(
+        image: &ImageData<P>,
+        luminance: bool,
+        channels: Channels,
+    ) -> IntegralImage {
+        let values_per_pixel = if luminance {
+            1
+        } else {
+            channels.count() as usize
+        };
+        let width = image.width as usize + 1;
+        let size = width * (image.height as usize + 1) * values_per_pixel;
+        let mut sums = vec![0.0; size];
+        let mut squares = vec![0.0; size];
+        for y in 0..image.height as usize {
+            let mut row_sums = [0.0; 4];
+            let mut row_squares = [0.0; 4];
+            let row = image.get_pixels(0, y as u32, image.width as usize);
+            for (x, pixel) in row.chunks_exact(4).enumerate() {
+                let values = normalized_values(pixel, luminance);
+                let index = ((y + 1) * width + x + 1) * values_per_pixel;
+                let above = (y * width + x + 1) * values_per_pixel;
+                for c in 0..values_per_pixel {
+                    row_sums[c] += values[c];
+                    row_squares[c] += values[c] * values[c];
+                    sums[index + c] = sums[above + c] + row_sums[c];
+                    squares[index + c] = squares[above + c] + row_squares[c];
+                }
+            }
+        }
+        IntegralImage {
+            width,
+            luminance,
+            values_per_pixel,
+            sums,
+            squares,
+        }
+    }
+
+    /// Mean and inverse standard deviation of each value in the window, the latter is 0.0 for flat windows
+    fn window_scales(&self, x: u32, y: u32, width: u32, height: u32) -> [(f64, f64); 4] {
+        let corner =
+            |x: u32, y: u32| (y as usize * self.width + x as usize) * self.values_per_pixel;
+        let (top_left, top_right) = (corner(x, y), corner(x + width, y));
+        let (bottom_left, bottom_right) = (corner(x, y + height), corner(x + width, y + height));
+        let window_sum = |values: &[f64], c: usize| {
+            values[bottom_right + c] - values[top_right + c] - values[bottom_left + c]
+                + values[top_left + c]
+        };
+        let count = (width * height) as f64;
+        let mut scales = [(0.0, 0.0); 4];
+        for (c, scale) in scales.iter_mut().enumerate().take(self.values_per_pixel) {
+            let mean = window_sum(&self.sums, c) / count;
+            let mean_square = window_sum(&self.squares, c) / count;
+            // Rounding of the large sums can make the variance of a flat window slightly negative
+            let variance = (mean_square - mean * mean).max(0.0);
+            let inverse_deviation = if variance > FLAT_VARIANCE * mean_square {
+                1.0 / variance.sqrt()
+            } else {
+                0.0
+            };
+            *scale = (mean, inverse_deviation);
+        }
+        scales
+    }
+}
+
+/// Template with zero mean and unit variance of each normalized value, compared with normalized windows.
+struct NormalizedTemplate {
+    values: Vec<f64>,
+    // TSE of a unit squared difference, so the MSE of the result is (1 - correlation) / 2, between 0.0 and 1.0
+    weight: f64,
+}
+
+impl NormalizedTemplate {
+    fn new<P: Subpixel>(image: &ImageData<P>, luminance: bool, channels: Channels) -> Self {
+        let integral = IntegralImage::new(image, luminance, channels);
+        let values_per_pixel = integral.values_per_pixel;
+        let scales = integral.window_scales(0, 0, image.width, image.height);
+        let values = image
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| {
+                let values = normalized_values(pixel, luminance);
+                (0..values_per_pixel).map(move |c| (values[c] - scales[c].0) * scales[c].1)
+            })
+            .collect();
+        // Normalized values differ by 4 at most on average, while their MSE is relative to a full-scale difference
+        let weight = channels.count() as f64 * P::DEPTH.full_scale_squared_error()
+            / (4.0 * values_per_pixel as f64);
+        NormalizedTemplate { values, weight }
+    }
+
+    fn to_total_squared_error(&self, squared_error: f64) -> TotalSquaredError {
+        if squared_error.is_nan() {
+            // NaN pixels cannot match anything
+            TotalSquaredError::MAX
+        } else {
+            (squared_error * self.weight) as TotalSquaredError
+        }
+    }
+}
+
+// How positions are compared with a template, prepared once per search
+enum Comparison<'a> {
+    SquaredError(Channels),
+    MatchingPixels {
+        channels: Channels,
+        pixel_tolerance: f64,
+    },
+    Normalized {
+        windows: &'a IntegralImage,
+        template: NormalizedTemplate,
+    },
+}
+
+pub struct ImageData<P: Subpixel = u8> {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<P>, // RGBA pixel data
 }
 
 fn subpixel_squared_error(a: u8, b: u8) -> TotalSquaredError {
@@ -27,172 +425,1768 @@ fn subpixel_squared_error(a: u8, b: u8) -> TotalSquaredError {
 }
 
 /*
-subpixel: 8b
-square error: 16b
-resolution like 1920x1080 needs additional 21b, i.e., 37b in total, so u32 is not enough
+Errors of all depths are converted to TotalSquaredError, so the results are handled the same way:
+u8 subpixel: 8b, square error: 16b
+u16 subpixel: 16b, square error: 32b
+f32 subpixel: fixed point with 32 fractional bits, values above 1.0 saturate
+4 channels need additional 2b and resolution like 1920x1080 additional 21b, so u32 is not enough even for u8.
+MAX_PIXELS (28b) keeps u16 within 32 + 2 + 28 = 62b.
 */
 type TotalSquaredError = u64;
 type TotalSquaredErrorFloat = f64; // less presice than TSE, but 53 bits of significand should be enough; f128 is not stable yet
 
-impl ImageData {
-    pub fn get_pixels(&self, x: u32, y: u32, count: usize) -> &[u8] {
-        let index = (y * self.width + x) as usize * 4;
-        &self.pixels[index..index + 4 * count]
+// 2^28 pixels keep both memory usage (1 GiB of RGBA8) and TotalSquaredError (32 + 2 + 28 bits) in check
+pub const MAX_PIXELS: u64 = 1 << 28;
+
+fn check_pixel_count(width: u32, height: u32) -> Result<u64, Error> {
+    let pixel_count = width as u64 * height as u64;
+    if pixel_count > MAX_PIXELS {
+        return Err(Error::ResourceLimit {
+            resource: "pixels".to_string(),
+            limit: MAX_PIXELS,
+            actual: pixel_count,
+        });
     }
+    Ok(pixel_count)
+}
 
-    pub fn from_image(image: &web_sys::HtmlImageElement) -> Result<ImageData, String> {
-        let canvas: web_sys::HtmlCanvasElement = document()
-            .create_element("canvas")
-            .map_err(|e| format!("error creating canvas: {:?}", e))?
-            .dyn_into::<web_sys::HtmlCanvasElement>()
-            .unwrap();
-        let ctx: web_sys::CanvasRenderingContext2d = canvas
-            .get_context("2d")
-            .map_err(|e| format!("error getting 2d context: {:?}", e))?
-            .unwrap()
-            .dyn_into::<web_sys::CanvasRenderingContext2d>()
-            .unwrap();
+/// Metadata of an image file that changes how its pixels are meant to be displayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageMetadata {
+    // EXIF orientation, NoTransforms when the file has none
+    pub orientation: Orientation,
+    // Description of the embedded ICC profile, None when the file has none
+    pub color_profile: Option<String>,
+}
 
-        let width = image.natural_width();
-        let height = image.natural_height();
+impl Default for ImageMetadata {
+    fn default() -> Self {
+        ImageMetadata {
+            orientation: Orientation::NoTransforms,
+            color_profile: None,
+        }
+    }
+}
 
-        // Set canvas size to match image
-        canvas.set_width(width);
-        canvas.set_height(height);
+impl ImageMetadata {
+    pub fn orientation_description(&self) -> &'static str {
+        match self.orientation {
+            Orientation::NoTransforms => "normal",
+            Orientation::Rotate90 => "rotated 90° clockwise",
+            Orientation::Rotate180 => "rotated 180°",
+            Orientation::Rotate270 => "rotated 90° counterclockwise",
+            Orientation::FlipHorizontal => "mirrored horizontally",
+            Orientation::FlipVertical => "mirrored vertically",
+            Orientation::Rotate90FlipH => "rotated 90° clockwise and mirrored",
+            Orientation::Rotate270FlipH => "rotated 90° counterclockwise and mirrored",
+        }
+    }
+}
 
-        ctx.draw_image_with_html_image_element(image, 0.0, 0.0)
-            .unwrap();
+/**
+ * Image decoded in the precision of its file, e.g., 16-bit PNG or float TIFF.
+ * It is converted to ImageData of a single depth for the search.
+ */
+#[derive(Clone)]
+pub struct DecodedImage {
+    image: ::image::DynamicImage,
+    metadata: ImageMetadata,
+}
+
+/// A named decoded image to be searched for, optionally with its own threshold and result cap.
+pub struct DecodedTemplate<'a> {
+    pub name: String,
+    pub image: &'a DecodedImage,
+    // Overrides of the defaults from SearchConfig, see SearchConfig::for_template
+    pub max_mse: Option<f64>,
+    pub max_results: Option<u16>,
+}
+
+fn decoder<'a>(name: &str, bytes: &'a [u8]) -> Result<impl ImageDecoder + use<'a>, Error> {
+    let decode_error = |e: ::image::ImageError| Error::Decode {
+        image: name.to_string(),
+        reason: e.to_string(),
+    };
+    let mut reader = ::image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| decode_error(e.into()))?;
+    // The size is checked by check_pixel_count, the default limits are lower than ours
+    reader.no_limits();
+    reader.into_decoder().map_err(decode_error)
+}
+
+// Broken metadata is ignored like in browsers, the pixels can be decoded without it
+fn read_metadata(
+    name: &str,
+    decoder: &mut impl ImageDecoder,
+) -> (ImageMetadata, Option<moxcms::ColorProfile>) {
+    let orientation = decoder.orientation().unwrap_or_else(|err| {
+        log::warn!("Ignoring orientation of {}: {}", name, err);
+        Orientation::NoTransforms
+    });
+    let icc_profile = decoder.icc_profile().unwrap_or_else(|err| {
+        log::warn!("Ignoring color profile of {}: {}", name, err);
+        None
+    });
+    let (color_profile, profile) =
+        match icc_profile.map(|icc| moxcms::ColorProfile::new_from_slice(&icc)) {
+            None => (None, None),
+            Some(Err(err)) => {
+                log::warn!("Ignoring unreadable color profile of {}: {}", name, err);
+                (
+                    Some("unreadable ICC profile (not applied)".to_string()),
+                    None,
+                )
+            }
+            // Other color spaces (e.g., CMYK) are converted to RGB by the decoder itself
+            Some(Ok(profile)) if profile.color_space != moxcms::DataColorSpace::Rgb => (
+                Some(format!("{} (not applied)", profile_description(&profile))),
+                None,
+            ),
+            Some(Ok(profile)) => (Some(profile_description(&profile)), Some(profile)),
+        };
+    let metadata = ImageMetadata {
+        orientation,
+        color_profile,
+    };
+    (metadata, profile)
+}
+
+fn profile_description(profile: &moxcms::ColorProfile) -> String {
+    let description = match &profile.description {
+        Some(moxcms::ProfileText::PlainString(text)) => text.clone(),
+        Some(moxcms::ProfileText::Localizable(texts)) => {
+            texts.first().map(|t| t.value.clone()).unwrap_or_default()
+        }
+        Some(moxcms::ProfileText::Description(text)) => text.ascii_string.clone(),
+        None => String::new(),
+    };
+    let description = description.trim_end_matches('\0').trim();
+    if description.is_empty() {
+        "unnamed ICC profile".to_string()
+    } else {
+        description.to_string()
+    }
+}
+
+// Converts RGB(A) pixels to sRGB in the depth of the image, so no precision is lost
+fn convert_to_srgb(
+    name: &str,
+    image: ::image::DynamicImage,
+    profile: &moxcms::ColorProfile,
+) -> Result<::image::DynamicImage, Error> {
+    let error = |reason: String| Error::Decode {
+        image: name.to_string(),
+        reason: format!("error applying the color profile: {}", reason),
+    };
+    let cms_error = |e: moxcms::CmsError| error(e.to_string());
+    let size_error = || error("the converted pixels do not match the image size".to_string());
+    let srgb = moxcms::ColorProfile::new_srgb();
+    let layout = moxcms::Layout::Rgba;
+    let options = moxcms::TransformOptions::default();
+    let (width, height) = (image.width(), image.height());
+    Ok(match DecodedImage::depth_of(&image) {
+        Depth::U8 => {
+            let source = image.into_rgba8().into_raw();
+            let mut converted = vec![0; source.len()];
+            profile
+                .create_transform_8bit(layout, &srgb, layout, options)
+                .map_err(cms_error)?
+                .transform(&source, &mut converted)
+                .map_err(cms_error)?;
+            ::image::DynamicImage::ImageRgba8(
+                ::image::RgbaImage::from_raw(width, height, converted).ok_or_else(size_error)?,
+            )
+        }
+        Depth::U16 => {
+            let source = image.into_rgba16().into_raw();
+            let mut converted = vec![0; source.len()];
+            profile
+                .create_transform_16bit(layout, &srgb, layout, options)
+                .map_err(cms_error)?
+                .transform(&source, &mut converted)
+                .map_err(cms_error)?;
+            ::image::DynamicImage::ImageRgba16(
+                ::image::ImageBuffer::from_raw(width, height, converted).ok_or_else(size_error)?,
+            )
+        }
+        Depth::F32 => {
+            let source = image.into_rgba32f().into_raw();
+            let mut converted = vec![0.0; source.len()];
+            profile
+                .create_transform_f32(layout, &srgb, layout, options)
+                .map_err(cms_error)?
+                .transform(&source, &mut converted)
+                .map_err(cms_error)?;
+            ::image::DynamicImage::ImageRgba32F(
+                ::image::ImageBuffer::from_raw(width, height, converted).ok_or_else(size_error)?,
+            )
+        }
+    })
+}
+
+fn apply_image_metadata(
+    name: &str,
+    mut image: ::image::DynamicImage,
+    metadata: &ImageMetadata,
+    profile: Option<&moxcms::ColorProfile>,
+) -> Result<::image::DynamicImage, Error> {
+    if let Some(profile) = profile {
+        image = convert_to_srgb(name, image, profile)?;
+    }
+    image.apply_orientation(metadata.orientation);
+    Ok(image)
+}
+
+// All frames are kept in memory at once, so their total size is limited like the size of a single image
+fn check_frames_pixel_count(total: &mut u64, width: u32, height: u32) -> Result<(), Error> {
+    *total += check_pixel_count(width, height)?;
+    if *total > MAX_PIXELS {
+        return Err(Error::ResourceLimit {
+            resource: "pixels in all frames".to_string(),
+            limit: MAX_PIXELS,
+            actual: *total,
+        });
+    }
+    Ok(())
+}
+
+fn animation_frames<'a>(
+    name: &str,
+    decoder: impl ::image::AnimationDecoder<'a>,
+) -> Result<Vec<::image::DynamicImage>, Error> {
+    let mut total_pixels = 0;
+    let mut frames = Vec::new();
+    for frame in decoder.into_frames() {
+        let buffer = frame
+            .map_err(|e| Error::Decode {
+                image: name.to_string(),
+                reason: format!("frame {}: {}", frames.len() + 1, e),
+            })?
+            .into_buffer();
+        check_frames_pixel_count(&mut total_pixels, buffer.width(), buffer.height())?;
+        frames.push(::image::DynamicImage::ImageRgba8(buffer));
+    }
+    Ok(frames)
+}
+
+// None for single-page files, the image crate decodes them with more color types (e.g., CMYK)
+fn tiff_pages(name: &str, bytes: &[u8]) -> Result<Option<Vec<::image::DynamicImage>>, Error> {
+    use ::image::DynamicImage;
+    use tiff::ColorType;
+    use tiff::decoder::DecodingResult;
+
+    let mut page = 0;
+    let decode_error = |page: usize, reason: String| Error::Decode {
+        image: name.to_string(),
+        reason: format!("page {}: {}", page + 1, reason),
+    };
+    let mut decoder = tiff::decoder::Decoder::new(std::io::Cursor::new(bytes))
+        .map_err(|e| decode_error(page, e.to_string()))?
+        .with_limits(tiff::decoder::Limits::unlimited());
+    if !decoder.more_images() {
+        return Ok(None);
+    }
+    let mut total_pixels = 0;
+    let mut pages = Vec::new();
+    loop {
+        let tiff_error = |e: tiff::TiffError| decode_error(page, e.to_string());
+        let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+        check_frames_pixel_count(&mut total_pixels, width, height)?;
+        let color_type = decoder.colortype().map_err(tiff_error)?;
+        let image = match (color_type, decoder.read_image().map_err(tiff_error)?) {
+            (ColorType::Gray(8), DecodingResult::U8(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageLuma8)
+            }
+            (ColorType::Gray(16), DecodingResult::U16(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageLuma16)
+            }
+            (ColorType::GrayA(8), DecodingResult::U8(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageLumaA8)
+            }
+            (ColorType::GrayA(16), DecodingResult::U16(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageLumaA16)
+            }
+            (ColorType::RGB(8), DecodingResult::U8(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageRgb8)
+            }
+            (ColorType::RGB(16), DecodingResult::U16(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageRgb16)
+            }
+            (ColorType::RGB(32), DecodingResult::F32(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageRgb32F)
+            }
+            (ColorType::RGBA(8), DecodingResult::U8(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageRgba8)
+            }
+            (ColorType::RGBA(16), DecodingResult::U16(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageRgba16)
+            }
+            (ColorType::RGBA(32), DecodingResult::F32(p)) => {
+                ::image::ImageBuffer::from_raw(width, height, p).map(DynamicImage::ImageRgba32F)
+            }
+            (color_type, _) => {
+                return Err(decode_error(
+                    page,
+                    format!("unsupported color type {:?}", color_type),
+                ));
+            }
+        }
+        .ok_or_else(|| decode_error(page, "unexpected size of the pixel data".to_string()))?;
+        pages.push(image);
+        if !decoder.more_images() {
+            return Ok(Some(pages));
+        }
+        decoder.next_image().map_err(tiff_error)?;
+        page += 1;
+    }
+}
+
+impl DecodedImage {
+    /**
+     * Decodes an encoded image (PNG, JPEG, GIF, BMP, WebP or TIFF) in Rust,
+     * so the pixels are the same in the browser and in native builds.
+     * With apply_metadata, the image is rotated by its EXIF orientation and its ICC profile is converted to sRGB,
+     * so it matches what photo viewers and editors display.
+     */
+    pub fn decode(name: &str, bytes: &[u8], apply_metadata: bool) -> Result<DecodedImage, Error> {
+        let decode_error = |e: ::image::ImageError| Error::Decode {
+            image: name.to_string(),
+            reason: e.to_string(),
+        };
+        let mut decoder = decoder(name, bytes)?;
+        // The header is enough to reject huge images before allocating their pixels
+        let (width, height) = decoder.dimensions();
+        check_pixel_count(width, height)?;
+        let (metadata, profile) = read_metadata(name, &mut decoder);
+        let mut image = ::image::DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+        if apply_metadata {
+            image = apply_image_metadata(name, image, &metadata, profile.as_ref())?;
+        }
+        Ok(DecodedImage { image, metadata })
+    }
+
+    /**
+     * Decodes every frame of an animated GIF or APNG, or every page of a multi-page TIFF.
+     * Other images have a single frame, the same as from decode(). Frames of animations are composed
+     * the way they are displayed, so they all have the size of the whole animation.
+     */
+    pub fn decode_frames(
+        name: &str,
+        bytes: &[u8],
+        apply_metadata: bool,
+    ) -> Result<Vec<DecodedImage>, Error> {
+        let decode_error = |e: ::image::ImageError| Error::Decode {
+            image: name.to_string(),
+            reason: e.to_string(),
+        };
+        let format = ::image::ImageReader::new(std::io::Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|e| decode_error(e.into()))?
+            .format();
+        let images = match format {
+            Some(::image::ImageFormat::Gif) => {
+                let mut decoder =
+                    ::image::codecs::gif::GifDecoder::new(std::io::Cursor::new(bytes))
+                        .map_err(decode_error)?;
+                decoder
+                    .set_limits(::image::Limits::no_limits())
+                    .map_err(decode_error)?;
+                animation_frames(name, decoder)?
+            }
+            Some(::image::ImageFormat::Png) => {
+                let decoder = ::image::codecs::png::PngDecoder::new(std::io::Cursor::new(bytes))
+                    .map_err(decode_error)?;
+                if decoder.is_apng().map_err(decode_error)? {
+                    animation_frames(name, decoder.apng().map_err(decode_error)?)?
+                } else {
+                    return Ok(vec![DecodedImage::decode(name, bytes, apply_metadata)?]);
+                }
+            }
+            Some(::image::ImageFormat::Tiff) => match tiff_pages(name, bytes)? {
+                Some(pages) => pages,
+                None => return Ok(vec![DecodedImage::decode(name, bytes, apply_metadata)?]),
+            },
+            _ => return Ok(vec![DecodedImage::decode(name, bytes, apply_metadata)?]),
+        };
+        if images.is_empty() {
+            return Err(Error::Decode {
+                image: name.to_string(),
+                reason: "the animation has no frames".to_string(),
+            });
+        }
+        if images.len() == 1 {
+            // A still GIF, decoded the usual way for consistency
+            return Ok(vec![DecodedImage::decode(name, bytes, apply_metadata)?]);
+        }
+        // The metadata of the first frame applies to the whole file
+        let (metadata, profile) = read_metadata(name, &mut decoder(name, bytes)?);
+        images
+            .into_iter()
+            .map(|image| {
+                let image = if apply_metadata {
+                    apply_image_metadata(name, image, &metadata, profile.as_ref())?
+                } else {
+                    image
+                };
+                Ok(DecodedImage {
+                    image,
+                    metadata: metadata.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Part of the image in its precision, e.g., a template defined by a region of the main image.
+    pub fn crop(&self, rect: &Rect) -> Result<DecodedImage, Error> {
+        let fits = |start: u32, size: u32, limit: u32| {
+            start.checked_add(size).is_some_and(|end| end <= limit)
+        };
+        if !fits(rect.x, rect.width, self.width()) || !fits(rect.y, rect.height, self.height()) {
+            return Err(Error::invalid_parameter(
+                "template region",
+                format!(
+                    "{} is not inside the {}×{} image",
+                    rect,
+                    self.width(),
+                    self.height()
+                ),
+            ));
+        }
+        Ok(DecodedImage {
+            image: self.image.crop_imm(rect.x, rect.y, rect.width, rect.height),
+            metadata: self.metadata.clone(),
+        })
+    }
+
+    /// The image as 8-bit RGBA PNG, e.g., for displaying a single frame of an animation
+    pub fn to_png(&self) -> Result<Vec<u8>, Error> {
+        use ::image::ImageEncoder;
+        let mut png = Vec::new();
+        // Displayed frames are temporary, so speed matters more than size
+        ::image::codecs::png::PngEncoder::new_with_quality(
+            &mut png,
+            ::image::codecs::png::CompressionType::Fast,
+            ::image::codecs::png::FilterType::NoFilter,
+        )
+        .write_image(
+            &self.image.to_rgba8(),
+            self.width(),
+            self.height(),
+            ::image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| Error::Browser(format!("error encoding a frame: {}", e)))?;
+        Ok(png)
+    }
+
+    /// Reads only the metadata, e.g., for displaying it before the image is searched.
+    pub fn read_metadata(name: &str, bytes: &[u8]) -> Result<ImageMetadata, Error> {
+        let mut decoder = decoder(name, bytes)?;
+        Ok(read_metadata(name, &mut decoder).0)
+    }
+
+    /// Metadata of the file, whether it has been applied or not
+    pub fn metadata(&self) -> &ImageMetadata {
+        &self.metadata
+    }
+
+    pub fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    pub fn depth(&self) -> Depth {
+        DecodedImage::depth_of(&self.image)
+    }
+
+    fn depth_of(image: &::image::DynamicImage) -> Depth {
+        let color = image.color();
+        match color.bytes_per_pixel() / color.channel_count() {
+            1 => Depth::U8,
+            2 => Depth::U16,
+            _ => Depth::F32,
+        }
+    }
+
+    pub fn to_image_data<P: Subpixel>(&self) -> Result<ImageData<P>, Error> {
+        ImageData::new(self.width(), self.height(), P::from_image(&self.image))
+    }
+
+    /// The templates are searched in the depth of the most precise image, so no image loses precision.
+    pub fn search_depth(&self, templates: &[DecodedTemplate]) -> Depth {
+        templates
+            .iter()
+            .map(|t| t.image.depth())
+            .fold(self.depth(), Depth::max)
+    }
 
+    /// Empty results for each template, e.g., for collecting matches reported by SearchEvents.
+    pub fn new_search_results(
+        &self,
+        templates: &[DecodedTemplate],
+        config: &SearchConfig,
+    ) -> Vec<SearchResults> {
+        let depth = self.search_depth(templates);
+        templates
+            .iter()
+            .map(|t| {
+                new_search_results(
+                    &t.name,
+                    (t.image.width(), t.image.height()),
+                    (self.width(), self.height()),
+                    &config.for_template(t.max_mse, t.max_results),
+                    depth,
+                )
+            })
+            .collect()
+    }
+
+    /// Like ImageData::error_map, depth should be the search_depth of the search that found the match.
+    pub fn error_map(
+        &self,
+        template: &DecodedTemplate,
+        x: u32,
+        y: u32,
+        channels: Channels,
+        depth: Depth,
+    ) -> Result<ErrorMap, Error> {
+        let region = Rect {
+            x,
+            y,
+            width: template.image.width(),
+            height: template.image.height(),
+        };
+        let region = self.crop(&region)?;
+        match depth {
+            Depth::U8 => region.error_map_as::<u8>(template.image, channels),
+            Depth::U16 => region.error_map_as::<u16>(template.image, channels),
+            Depth::F32 => region.error_map_as::<f32>(template.image, channels),
+        }
+    }
+
+    fn error_map_as<P: Subpixel>(
+        &self,
+        template: &DecodedImage,
+        channels: Channels,
+    ) -> Result<ErrorMap, Error> {
+        Ok(self
+            .to_image_data::<P>()?
+            .error_map(&template.to_image_data::<P>()?, 0, 0, channels))
+    }
+
+    /// Like ImageData::find_subimage, the images are converted to the search depth first.
+    pub async fn find_subimage<F, E>(
+        &self,
+        templates: &[DecodedTemplate<'_>],
+        config: &SearchConfig,
+        progress_callback: F,
+        event_callback: E,
+        mask: Option<&Mask>,
+        cancellation: &CancellationToken,
+    ) -> Result<SearchOutcome, Error>
+    where
+        F: Fn(f32) + 'static,
+        E: Fn(usize, SearchEvent) + 'static,
+    {
+        match self.search_depth(templates) {
+            Depth::U8 => {
+                self.find_subimage_as::<u8, F, E>(
+                    templates,
+                    config,
+                    progress_callback,
+                    event_callback,
+                    mask,
+                    cancellation,
+                )
+                .await
+            }
+            Depth::U16 => {
+                self.find_subimage_as::<u16, F, E>(
+                    templates,
+                    config,
+                    progress_callback,
+                    event_callback,
+                    mask,
+                    cancellation,
+                )
+                .await
+            }
+            Depth::F32 => {
+                self.find_subimage_as::<f32, F, E>(
+                    templates,
+                    config,
+                    progress_callback,
+                    event_callback,
+                    mask,
+                    cancellation,
+                )
+                .await
+            }
+        }
+    }
+
+    async fn find_subimage_as<P: Subpixel, F, E>(
+        &self,
+        templates: &[DecodedTemplate<'_>],
+        config: &SearchConfig,
+        progress_callback: F,
+        event_callback: E,
+        mask: Option<&Mask>,
+        cancellation: &CancellationToken,
+    ) -> Result<SearchOutcome, Error>
+    where
+        F: Fn(f32) + 'static,
+        E: Fn(usize, SearchEvent) + 'static,
+    {
+        let conversion_start = now_ms();
+        let main = self.to_image_data::<P>()?;
+        let images = templates
+            .iter()
+            .map(|t| t.image.to_image_data::<P>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let typed_templates: Vec<Template<P>> = templates
+            .iter()
+            .zip(images.iter())
+            .map(|(t, image)| Template {
+                name: t.name.clone(),
+                image,
+                max_mse: t.max_mse,
+                max_results: t.max_results,
+            })
+            .collect();
+        let conversion_duration_ms = now_ms() - conversion_start;
+        let mut outcome = main
+            .find_subimage(
+                &typed_templates,
+                config,
+                progress_callback,
+                event_callback,
+                mask,
+                cancellation,
+            )
+            .await?;
+        outcome.statistics.phases.insert(
+            0,
+            PhaseTiming {
+                name: format!("convert to {:?}", P::DEPTH),
+                duration_ms: conversion_duration_ms,
+            },
+        );
+        Ok(outcome)
+    }
+}
+
+// Smallest TSE not below the MSE, so positions with exactly that MSE are accepted
+fn mse_to_tse(
+    mse: f64,
+    squared_errors_divisor: u32,
+    full_scale_squared_error: f64,
+) -> TotalSquaredError {
+    ((mse as TotalSquaredErrorFloat)
+        * (squared_errors_divisor as TotalSquaredErrorFloat)
+        * full_scale_squared_error)
+        .ceil() as TotalSquaredError
+}
+
+// Threshold accepting any position except the masked ones, which get TotalSquaredError::MAX
+const UNLIMITED_TSE: TotalSquaredError = TotalSquaredError::MAX - 1;
+
+/// Candidates kept for each result when the threshold is picked automatically, so the rest of the errors is known.
+pub const AUTO_THRESHOLD_CANDIDATES_PER_RESULT: u16 = 4;
+pub const AUTO_THRESHOLD_MIN_CANDIDATES: u16 = 32;
+// The errors of matches and of the background must differ at least this many times
+const AUTO_THRESHOLD_MIN_GAP: f64 = 2.0;
+// Added to the errors before comparing them, so exact matches don't make every other gap infinitely large
+const AUTO_THRESHOLD_EPSILON: f64 = 1e-6;
+
+/**
+ * Maximum MSE separating the best positions from the background, given the MSEs of the best candidates in ascending order.
+ * The cut-off is placed in the largest relative gap between consecutive errors, halfway on a logarithmic scale.
+ * Returns 0.0 (exact matches only) when no gap is large enough, e.g., when the template doesn't occur at all.
+ */
+pub fn auto_threshold(sorted_mse: &[f64]) -> f64 {
+    let shifted = |mse: f64| mse.max(0.0) + AUTO_THRESHOLD_EPSILON;
+    sorted_mse
+        .windows(2)
+        .map(|pair| (pair[0], pair[1], shifted(pair[1]) / shifted(pair[0])))
+        .filter(|&(_, _, ratio)| ratio >= AUTO_THRESHOLD_MIN_GAP)
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map_or(0.0, |(below, above, _)| {
+            ((shifted(below) * shifted(above)).sqrt() - AUTO_THRESHOLD_EPSILON).max(below)
+        })
+}
+
+fn new_search_results(
+    template_name: &str,
+    (template_width, template_height): (u32, u32),
+    (main_width, main_height): (u32, u32),
+    config: &SearchConfig,
+    depth: Depth,
+) -> SearchResults {
+    let squared_errors_divisor = template_width * template_height * config.channels.count();
+    let full_scale_squared_error = depth.full_scale_squared_error();
+    // Without a fixed threshold, only the capacity tightens the threshold as the best positions are found
+    let (max_tse, capacity) = match config.threshold_mode {
+        ThresholdMode::Fixed => (
+            mse_to_tse(
+                config.max_mse,
+                squared_errors_divisor,
+                full_scale_squared_error,
+            ),
+            config.max_results,
+        ),
+        // The automatic threshold is picked from the best candidates
+        ThresholdMode::Automatic => (
+            UNLIMITED_TSE,
+            config
+                .max_results
+                .saturating_mul(AUTO_THRESHOLD_CANDIDATES_PER_RESULT)
+                .max(AUTO_THRESHOLD_MIN_CANDIDATES),
+        ),
+        ThresholdMode::BestK => (UNLIMITED_TSE, config.max_results),
+    };
+    log::info!("{}: max_tse: {}", template_name, max_tse);
+    log::info!(
+        "{}: MSE for max_tse: {}",
+        template_name,
+        (max_tse as f64) / (squared_errors_divisor as f64) / full_scale_squared_error
+    );
+    SearchResults::new(
+        template_name.to_string(),
+        capacity,
+        template_width,
+        template_height,
+        main_width,
+        main_height,
+        squared_errors_divisor,
+        full_scale_squared_error,
+        max_tse,
+    )
+}
+
+/// Allows stopping a running search from outside, e.g., by a button.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Rc<Cell<bool>>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.set(true);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.get()
+    }
+}
+
+/**
+ * Errors normalized like MSE, either of each pixel of a match (their mean is the MSE of the match),
+ * or of each top-left position of a template in the main image. NaN marks positions that were not compared,
+ * e.g., outside of the search regions.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorMap {
+    width: u32,
+    height: u32,
+    errors: Vec<f32>, // Row by row, single precision halves the memory of large landscapes
+}
+
+impl ErrorMap {
+    fn not_compared(width: u32, height: u32) -> ErrorMap {
+        ErrorMap {
+            width,
+            height,
+            errors: vec![f32::NAN; (width * height) as usize],
+        }
+    }
+    fn set(&mut self, x: u32, y: u32, error: f64) {
+        self.errors[(y * self.width + x) as usize] = error as f32;
+    }
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.errors[(y * self.width + x) as usize]
+    }
+    /// The largest compared error, NaN is ignored
+    pub fn get_max(&self) -> f32 {
+        self.errors.iter().copied().fold(0.0, f32::max)
+    }
+    pub fn get_mse(&self) -> f64 {
+        self.errors.iter().map(|&e| e as f64).sum::<f64>() / self.errors.len().max(1) as f64
+    }
+
+    /// Mean errors of a columns × rows grid of the map, row by row, NaN for cells without compared positions
+    pub fn region_mse(&self, columns: u32, rows: u32) -> Vec<f64> {
+        let bounds =
+            |index: u32, count: u32, size: u32| index * size / count..(index + 1) * size / count;
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let (sum, count) = bounds(row, rows, self.height)
+                    .flat_map(|y| bounds(column, columns, self.width).map(move |x| (x, y)))
+                    .map(|(x, y)| self.get(x, y))
+                    .filter(|error| !error.is_nan())
+                    .fold((0.0, 0), |(sum, count), error| {
+                        (sum + error as f64, count + 1)
+                    });
+                if count == 0 {
+                    f64::NAN
+                } else {
+                    sum / count as f64
+                }
+            })
+            .collect()
+    }
+
+    // Errors relative to the largest one, row by row, None where the position was not compared
+    fn relative_errors(&self) -> impl Iterator<Item = Option<f32>> + '_ {
+        let max = self.get_max();
+        self.errors.iter().map(move |&error| {
+            if error.is_nan() {
+                None
+            } else if max > 0.0 {
+                Some((error / max).clamp(0.0, 1.0))
+            } else {
+                Some(0.0)
+            }
+        })
+    }
+
+    /**
+     * Heatmap of the errors relative to the worst pixel: black, red, yellow and white for the worst one.
+     * A perfect match is all black, positions that were not compared are transparent.
+     */
+    pub fn to_heatmap(&self) -> DecodedImage {
+        let pixels = self
+            .relative_errors()
+            .flat_map(|relative| {
+                let Some(relative) = relative else {
+                    return [0, 0, 0, 0];
+                };
+                // Each third of the scale raises one channel, so small differences are still visible
+                let channel =
+                    |start: f32| ((relative * 3.0 - start).clamp(0.0, 1.0) * 255.0).round() as u8;
+                [channel(0.0), channel(1.0), channel(2.0), 255]
+            })
+            .collect();
+        // The buffer has the size of the map, so it cannot be rejected
+        let heatmap = ::image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .unwrap_or_else(|| ::image::RgbaImage::new(self.width, self.height));
+        DecodedImage {
+            image: ::image::DynamicImage::ImageRgba8(heatmap),
+            metadata: ImageMetadata::default(),
+        }
+    }
+
+    /**
+     * 16-bit grayscale PNG with the largest error as white, e.g., for analysis in other tools.
+     * Positions that were not compared are white too. to_raw_f32 keeps the values themselves.
+     */
+    pub fn to_gray16_png(&self) -> Result<Vec<u8>, Error> {
+        use ::image::ImageEncoder;
+        // The encoder takes 16-bit samples as bytes in native endianness
+        let samples: Vec<u8> = self
+            .relative_errors()
+            .map(|relative| relative.map_or(u16::MAX, |r| (r * u16::MAX as f32).round() as u16))
+            .flat_map(u16::to_ne_bytes)
+            .collect();
+        let mut png = Vec::new();
+        ::image::codecs::png::PngEncoder::new(&mut png)
+            .write_image(
+                &samples,
+                self.width,
+                self.height,
+                ::image::ExtendedColorType::L16,
+            )
+            .map_err(|e| Error::Browser(format!("error encoding the error heatmap: {}", e)))?;
+        Ok(png)
+    }
+
+    /// The errors as little-endian 32-bit floats, row by row, NaN where the position was not compared
+    pub fn to_raw_f32(&self) -> Vec<u8> {
+        self.errors.iter().flat_map(|e| e.to_le_bytes()).collect()
+    }
+}
+
+impl<P: Subpixel> ImageData<P> {
+    pub fn new(width: u32, height: u32, pixels: Vec<P>) -> Result<ImageData<P>, Error> {
+        let pixel_count = check_pixel_count(width, height)?;
+        if pixels.len() as u64 != pixel_count * 4 {
+            return Err(Error::invalid_parameter(
+                "pixel data",
+                format!(
+                    "expected {} RGBA values for {}x{} image, got {}",
+                    pixel_count * 4,
+                    width,
+                    height,
+                    pixels.len()
+                ),
+            ));
+        }
         Ok(ImageData {
             width,
             height,
-            pixels: ctx
-                .get_image_data(0.0, 0.0, width.into(), height.into())
-                .unwrap()
-                .data()
-                .to_vec(),
+            pixels,
         })
     }
 
+    pub fn get_pixels(&self, x: u32, y: u32, count: usize) -> &[P] {
+        let index = (y * self.width + x) as usize * 4;
+        &self.pixels[index..index + 4 * count]
+    }
+
     /**
      * Calculate the total square error between the main image and a search image
      * starting at the given coordinates.
      * max_tse is just a hint, the function may return higher value when max_tse is exceeded.
+     * Positions overlapping the mask get the maximum error, so they are never reported.
      */
     pub fn total_squared_error(
         &self,
-        search_image: &ImageData,
+        search_image: &ImageData<P>,
         x: u32,
         y: u32,
         max_tse: TotalSquaredError,
+        mask: Option<&Mask>,
+        channels: Channels,
     ) -> TotalSquaredError {
+        self.total_squared_error_with_rows(search_image, x, y, max_tse, mask, channels)
+            .0
+    }
+
+    /**
+     * Largest squared error of a single compared subpixel between the main image and a search image
+     * starting at the given coordinates, in the units of TotalSquaredError.
+     * Like max_tse, limit is just a hint, the function returns as soon as a subpixel exceeds it.
+     */
+    pub fn max_squared_deviation(
+        &self,
+        search_image: &ImageData<P>,
+        x: u32,
+        y: u32,
+        limit: TotalSquaredError,
+        channels: Channels,
+    ) -> TotalSquaredError {
+        let mut max: TotalSquaredError = 0;
+        for dy in 0..search_image.height {
+            let main_pixels = self.get_pixels(x, y + dy, search_image.width as usize);
+            let search_pixels = search_image.get_pixels(0, dy, search_image.width as usize);
+            let subpixels: Box<dyn Iterator<Item = (&P, &P)>> = match channels {
+                Channels::Rgba => Box::new(main_pixels.iter().zip(search_pixels)),
+                Channels::Rgb => Box::new(
+                    main_pixels
+                        .chunks_exact(4)
+                        .zip(search_pixels.chunks_exact(4))
+                        .flat_map(|(m, s)| m[..3].iter().zip(&s[..3])),
+                ),
+            };
+            for (m, s) in subpixels {
+                max = max.max(P::to_total_squared_error(P::squared_error(*m, *s)));
+                if max > limit {
+                    return max;
+                }
+            }
+        }
+        max
+    }
+
+    // Comparisons of the templates by the metric of the config, sharing the window statistics of this image
+    fn comparisons<'a>(
+        templates: &[Template<'_, P>],
+        config: &SearchConfig,
+        windows: Option<&'a IntegralImage>,
+    ) -> Vec<Comparison<'a>> {
+        templates
+            .iter()
+            .map(|template| match windows {
+                Some(windows) => Comparison::Normalized {
+                    windows,
+                    template: NormalizedTemplate::new(
+                        template.image,
+                        windows.luminance,
+                        config.channels,
+                    ),
+                },
+                None if config.metric == Metric::MatchingPixels => Comparison::MatchingPixels {
+                    channels: config.channels,
+                    pixel_tolerance: config.pixel_tolerance,
+                },
+                None => Comparison::SquaredError(config.channels),
+            })
+            .collect()
+    }
+
+    /// Error of the position by the comparison, with the number of template rows read before returning.
+    fn position_error_with_rows(
+        &self,
+        search_image: &ImageData<P>,
+        x: u32,
+        y: u32,
+        max_tse: TotalSquaredError,
+        mask: Option<&Mask>,
+        comparison: &Comparison,
+    ) -> (TotalSquaredError, u32) {
+        match comparison {
+            Comparison::SquaredError(channels) => {
+                self.total_squared_error_with_rows(search_image, x, y, max_tse, mask, *channels)
+            }
+            Comparison::MatchingPixels {
+                channels,
+                pixel_tolerance,
+            } => self.differing_pixels_with_rows(
+                search_image,
+                x,
+                y,
+                max_tse,
+                mask,
+                *channels,
+                *pixel_tolerance,
+            ),
+            Comparison::Normalized { windows, template } => self.normalized_error_with_rows(
+                (search_image.width, search_image.height),
+                x,
+                y,
+                max_tse,
+                mask,
+                windows,
+                template,
+            ),
+        }
+    }
+
+    /**
+     * Like total_squared_error_with_rows, but the window and the template are normalized to zero mean
+     * and unit variance first, so a change of brightness and contrast doesn't matter.
+     * The statistics of the window come from the integral image, so it costs about as much as the squared error.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn normalized_error_with_rows(
+        &self,
+        (width, height): (u32, u32),
+        x: u32,
+        y: u32,
+        max_tse: TotalSquaredError,
+        mask: Option<&Mask>,
+        windows: &IntegralImage,
+        template: &NormalizedTemplate,
+    ) -> (TotalSquaredError, u32) {
+        let values_per_pixel = windows.values_per_pixel;
+        let scales = windows.window_scales(x, y, width, height);
+        let row_length = width as usize * values_per_pixel;
+        let mut squared_error = 0.0;
+        for dy in 0..height {
+            if mask.is_some_and(|m| m.overlaps_row(x, y + dy, width)) {
+                return (TotalSquaredError::MAX, dy);
+            }
+            let main_pixels = self.get_pixels(x, y + dy, width as usize);
+            let template_values = &template.values[dy as usize * row_length..][..row_length];
+            for (pixel, template_pixel) in main_pixels
+                .chunks_exact(4)
+                .zip(template_values.chunks_exact(values_per_pixel))
+            {
+                let values = normalized_values(pixel, windows.luminance);
+                for (c, template_value) in template_pixel.iter().enumerate() {
+                    let difference = (values[c] - scales[c].0) * scales[c].1 - template_value;
+                    squared_error += difference * difference;
+                }
+            }
+            let tse = template.to_total_squared_error(squared_error);
+            if tse > max_tse {
+                return (tse, dy + 1);
+            }
+        }
+        (template.to_total_squared_error(squared_error), height)
+    }
+
+    /**
+     * Like total_squared_error_with_rows, but each pixel whose MSE exceeds pixel_tolerance adds the error
+     * of a full-scale difference of all its compared subpixels, and the other pixels add nothing.
+     * The MSE of the result is then the fraction of differing pixels, and the position is still rejected
+     * as soon as there are too many of them.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn differing_pixels_with_rows(
+        &self,
+        search_image: &ImageData<P>,
+        x: u32,
+        y: u32,
+        max_tse: TotalSquaredError,
+        mask: Option<&Mask>,
+        channels: Channels,
+        pixel_tolerance: f64,
+    ) -> (TotalSquaredError, u32) {
+        let compared = channels.count() as usize;
+        let full_scale_squared_error = P::DEPTH.full_scale_squared_error();
+        let max_pixel_error =
+            mse_to_tse(pixel_tolerance, channels.count(), full_scale_squared_error);
+        let differing_pixel_error =
+            (channels.count() as f64 * full_scale_squared_error) as TotalSquaredError;
         let mut tse: TotalSquaredError = 0;
         for dy in 0..search_image.height {
+            if mask.is_some_and(|m| m.overlaps_row(x, y + dy, search_image.width)) {
+                return (TotalSquaredError::MAX, dy);
+            }
             let main_pixels = self.get_pixels(x, y + dy, search_image.width as usize);
             let search_pixels = search_image.get_pixels(0, dy, search_image.width as usize);
-            tse += main_pixels
-                .iter()
-                .zip(search_pixels)
-                .map(|(m, s)| subpixel_squared_error(*m, *s))
-                .sum::<TotalSquaredError>();
+            let differing = main_pixels
+                .chunks_exact(4)
+                .zip(search_pixels.chunks_exact(4))
+                .filter(|(m, s)| {
+                    let pixel_error = m[..compared]
+                        .iter()
+                        .zip(&s[..compared])
+                        .map(|(m, s)| P::squared_error(*m, *s))
+                        .sum::<P::Accumulator>();
+                    P::to_total_squared_error(pixel_error) > max_pixel_error
+                })
+                .count();
+            tse = tse.saturating_add(differing as TotalSquaredError * differing_pixel_error);
+            if tse > max_tse {
+                return (tse, dy + 1);
+            }
+        }
+        (tse, search_image.height)
+    }
+
+    /// Like total_squared_error, but also returns number of template rows read before returning.
+    fn total_squared_error_with_rows(
+        &self,
+        search_image: &ImageData<P>,
+        x: u32,
+        y: u32,
+        max_tse: TotalSquaredError,
+        mask: Option<&Mask>,
+        channels: Channels,
+    ) -> (TotalSquaredError, u32) {
+        let mut tse: TotalSquaredError = 0;
+        for dy in 0..search_image.height {
+            if mask.is_some_and(|m| m.overlaps_row(x, y + dy, search_image.width)) {
+                return (TotalSquaredError::MAX, dy);
+            }
+            let main_pixels = self.get_pixels(x, y + dy, search_image.width as usize);
+            let search_pixels = search_image.get_pixels(0, dy, search_image.width as usize);
+            let row_error = match channels {
+                Channels::Rgba => main_pixels
+                    .iter()
+                    .zip(search_pixels)
+                    .map(|(m, s)| P::squared_error(*m, *s))
+                    .sum::<P::Accumulator>(),
+                Channels::Rgb => main_pixels
+                    .chunks_exact(4)
+                    .zip(search_pixels.chunks_exact(4))
+                    .flat_map(|(m, s)| m[..3].iter().zip(&s[..3]))
+                    .map(|(m, s)| P::squared_error(*m, *s))
+                    .sum::<P::Accumulator>(),
+            };
+            tse = tse.saturating_add(P::to_total_squared_error(row_error));
 
             // We might do this in the inner cycle. It would be more precise, but with more overhead. Not sure which is better.
             if tse > max_tse {
-                return tse;
+                return (tse, dy + 1);
             }
         }
-        tse
+        (tse, search_image.height)
     }
 
-    pub async fn find_subimage<F>(
-        self: &ImageData,
-        search_image: &ImageData,
+    /**
+     * The errors summed by total_squared_error, kept for each pixel of the template,
+     * e.g., for showing why a match scored as it did. The mask is not taken into account,
+     * as masked positions are never reported.
+     */
+    pub fn error_map(
+        &self,
+        search_image: &ImageData<P>,
+        x: u32,
+        y: u32,
+        channels: Channels,
+    ) -> ErrorMap {
+        let divisor = channels.count() as f64 * P::DEPTH.full_scale_squared_error();
+        let mut errors = Vec::with_capacity((search_image.width * search_image.height) as usize);
+        for dy in 0..search_image.height {
+            let main_pixels = self.get_pixels(x, y + dy, search_image.width as usize);
+            let search_pixels = search_image.get_pixels(0, dy, search_image.width as usize);
+            for (m, s) in main_pixels
+                .chunks_exact(4)
+                .zip(search_pixels.chunks_exact(4))
+            {
+                let compared = channels.count() as usize;
+                let pixel_error = m[..compared]
+                    .iter()
+                    .zip(&s[..compared])
+                    .map(|(m, s)| P::squared_error(*m, *s))
+                    .sum::<P::Accumulator>();
+                errors.push((P::to_total_squared_error(pixel_error) as f64 / divisor) as f32);
+            }
+        }
+        ErrorMap {
+            width: search_image.width,
+            height: search_image.height,
+            errors,
+        }
+    }
+
+    /**
+     * Like error_map, but each pixel has its error by the comparison, so the mean of the map
+     * is the MSE of the position by the metric of the search, e.g., the fraction of differing pixels.
+     */
+    fn comparison_error_map(
+        &self,
+        search_image: &ImageData<P>,
+        x: u32,
+        y: u32,
+        comparison: &Comparison,
+    ) -> ErrorMap {
+        let errors = match comparison {
+            Comparison::SquaredError(channels) => {
+                return self.error_map(search_image, x, y, *channels);
+            }
+            Comparison::MatchingPixels {
+                channels,
+                pixel_tolerance,
+            } => {
+                let compared = channels.count() as usize;
+                let max_pixel_error = mse_to_tse(
+                    *pixel_tolerance,
+                    channels.count(),
+                    P::DEPTH.full_scale_squared_error(),
+                );
+                (0..search_image.height)
+                    .flat_map(|dy| {
+                        let main_pixels = self.get_pixels(x, y + dy, search_image.width as usize);
+                        let search_pixels =
+                            search_image.get_pixels(0, dy, search_image.width as usize);
+                        main_pixels
+                            .chunks_exact(4)
+                            .zip(search_pixels.chunks_exact(4))
+                            .map(move |(m, s)| {
+                                let pixel_error = m[..compared]
+                                    .iter()
+                                    .zip(&s[..compared])
+                                    .map(|(m, s)| P::squared_error(*m, *s))
+                                    .sum::<P::Accumulator>();
+                                if P::to_total_squared_error(pixel_error) > max_pixel_error {
+                                    1.0
+                                } else {
+                                    0.0
+                                }
+                            })
+                    })
+                    .collect()
+            }
+            Comparison::Normalized { windows, template } => {
+                let values_per_pixel = windows.values_per_pixel;
+                let scales = windows.window_scales(x, y, search_image.width, search_image.height);
+                // Normalized values differ by 4 at most on average, the same scale as the weight of the template
+                let divisor = 4.0 * values_per_pixel as f32;
+                (0..search_image.height)
+                    .flat_map(|dy| {
+                        self.get_pixels(x, y + dy, search_image.width as usize)
+                            .chunks_exact(4)
+                    })
+                    .zip(template.values.chunks_exact(values_per_pixel))
+                    .map(|(pixel, template_pixel)| {
+                        let values = normalized_values(pixel, windows.luminance);
+                        let squared_error: f64 = template_pixel
+                            .iter()
+                            .enumerate()
+                            .map(|(c, template_value)| {
+                                let difference =
+                                    (values[c] - scales[c].0) * scales[c].1 - template_value;
+                                difference * difference
+                            })
+                            .sum();
+                        squared_error as f32 / divisor
+                    })
+                    .collect()
+            }
+        };
+        ErrorMap {
+            width: search_image.width,
+            height: search_image.height,
+            errors,
+        }
+    }
+
+    /// Empty results for the template, e.g., for collecting matches reported by SearchEvents.
+    pub fn new_search_results(
+        &self,
+        template: &Template<P>,
+        config: &SearchConfig,
+    ) -> SearchResults {
+        new_search_results(
+            &template.name,
+            (template.image.width, template.image.height),
+            (self.width, self.height),
+            &config.for_template(template.max_mse, template.max_results),
+            P::DEPTH,
+        )
+    }
+
+    /**
+     * Searches all the templates in a single traversal of the main image. The config applies to all of them,
+     * except for the maximum difference and the result cap a template has of its own.
+     * Returns SearchResults for each template, in the same order as the templates, and statistics of the search.
+     * Changes of the results are reported to event_callback with the template index as they happen,
+     * so the matches can be displayed before the search finishes.
+     * The cancellation is checked once per row.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub async fn find_subimage<F, E>(
+        self: &ImageData<P>,
+        templates: &[Template<'_, P>],
+        config: &SearchConfig,
         progress_callback: F,
-        max_mse: f64,
-        max_results: u16,
-    ) -> Result<SearchResults, String>
+        event_callback: E,
+        mask: Option<&Mask>,
+        cancellation: &CancellationToken,
+    ) -> Result<SearchOutcome, Error>
     where
         F: Fn(f32) + 'static,
+        E: Fn(usize, SearchEvent) + 'static,
     {
-        let squared_errors_divisor = search_image.width * search_image.height * 4;
-        let max_tse = ((max_mse as TotalSquaredErrorFloat)
-            * (squared_errors_divisor as TotalSquaredErrorFloat)
-            * 65536.0)
-            .ceil() as TotalSquaredError;
-        let mut results = SearchResults::new(
-            max_results,
-            search_image.width,
-            search_image.height,
-            self.width,
-            self.height,
-            squared_errors_divisor,
-            max_tse,
-        );
-
-        let total_rows = self.height - search_image.height;
-        log::info!("max_tse: {}", max_tse);
-        log::info!(
-            "MSE for max_tse: {}",
-            (max_tse as f64) / (squared_errors_divisor as f64) / 65536.0
-        );
-
-        if self.height < search_image.height {
-            return Err("Main image height is smaller than search image height".to_string());
-        }
-        if self.width < search_image.width {
-            return Err("Main image width is smaller than search image width".to_string());
+        let mut statistics = SearchStatistics::default();
+        let setup_start = now_ms();
+        config.validate()?;
+        let template_configs: Vec<SearchConfig> = templates
+            .iter()
+            .map(|t| config.for_template(t.max_mse, t.max_results))
+            .collect();
+        for (template, template_config) in templates.iter().zip(&template_configs) {
+            template_config.validate()?;
+            if self.height < template.image.height || self.width < template.image.width {
+                return Err(Error::TemplateLargerThanImage {
+                    template: template.name.clone(),
+                    template_width: template.image.width,
+                    template_height: template.image.height,
+                    main_width: self.width,
+                    main_height: self.height,
+                });
+            }
         }
+        let mut results: Vec<SearchResults> = templates
+            .iter()
+            .zip(&template_configs)
+            .map(|(template, template_config)| {
+                new_search_results(
+                    &template.name,
+                    (template.image.width, template.image.height),
+                    (self.width, self.height),
+                    template_config,
+                    P::DEPTH,
+                )
+            })
+            .collect();
+
+        // half-open intervals of top-left positions shared by all templates; smaller templates have more positions
+        let y_end = templates
+            .iter()
+            .map(|t| self.height - t.image.height + 1)
+            .max()
+            .unwrap_or(0);
+        let x_end = templates
+            .iter()
+            .map(|t| self.width - t.image.width + 1)
+            .max()
+            .unwrap_or(0);
+        let total_rows = y_end.saturating_sub(1);
+        // Capped errors are compared against the initial threshold, which doesn't tighten with more matches
+        let caps: Vec<TotalSquaredError> = results.iter().map(|r| r.tse_threshold).collect();
+        let mut landscapes: Vec<ErrorMap> = match config.error_landscape {
+            ErrorLandscape::Off => Vec::new(),
+            ErrorLandscape::Capped | ErrorLandscape::Exact => templates
+                .iter()
+                .map(|t| {
+                    ErrorMap::not_compared(
+                        self.width - t.image.width + 1,
+                        self.height - t.image.height + 1,
+                    )
+                })
+                .collect(),
+        };
+        // Window statistics are shared by all the templates, the normalized templates are prepared once
+        let windows = match config.metric {
+            Metric::NormalizedChannels => Some(IntegralImage::new(self, false, config.channels)),
+            Metric::NormalizedLuminance => Some(IntegralImage::new(self, true, config.channels)),
+            Metric::Mse | Metric::MatchingPixels => None,
+        };
+        let comparisons = Self::comparisons(templates, config, windows.as_ref());
+        // The MSE of a match cannot exceed the square of its deviation, so the errors are still rejected early
+        let max_deviations: Vec<Option<TotalSquaredError>> = template_configs
+            .iter()
+            .map(|c| {
+                (c.threshold_mode == ThresholdMode::Fixed
+                    && c.threshold_unit == ThresholdUnit::MaxDeviation)
+                    .then(|| mse_to_tse(c.max_mse, 1, P::DEPTH.full_scale_squared_error()))
+            })
+            .collect();
+        // Best position of each template regardless of the threshold, tracked only while it has no match,
+        // with its distance: the squared deviation under a maximum deviation, otherwise its error
+        let mut nearest: Vec<Option<(TotalSquaredError, SearchResult)>> =
+            vec![None; templates.len()];
+        statistics.add_phase("setup", setup_start);
+
+        let scan_start = now_ms();
         // y comes first because of memory locality
-        // half-open interval, hence + 1 for the upper bound
-        for y in 0..(self.height - search_image.height + 1) {
+        for y in 0..y_end {
             // Update progress once per row
             let progress = y as f32 / total_rows as f32;
             progress_callback(progress);
 
             // allow tasks threads to do some work
             yield_now().await;
+            if cancellation.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
 
-            log::info!("Checking line {}", y);
-            // half-open interval, hence + 1 for the upper bound
-            for x in 0..(self.width - search_image.width + 1) {
-                let tse = self.total_squared_error(search_image, x, y, results.tse_threshold);
-                if tse <= results.tse_threshold {
-                    results.push(SearchResult { x, y, tse });
-                    log::info!(
-                        "pos ({}, {}) ({} pxs)",
-                        x,
-                        y,
-                        search_image.width * search_image.height
-                    );
+            for x_range in candidate_ranges(&config.regions, y, x_end) {
+                for x in x_range {
+                    for (template_index, (template, template_results)) in
+                        templates.iter().zip(results.iter_mut()).enumerate()
+                    {
+                        let search_image = template.image;
+                        if x + search_image.width > self.width
+                            || y + search_image.height > self.height
+                        {
+                            continue;
+                        }
+                        let max_tse = match config.error_landscape {
+                            ErrorLandscape::Off => template_results.tse_threshold,
+                            ErrorLandscape::Capped => caps[template_index],
+                            ErrorLandscape::Exact => TotalSquaredError::MAX,
+                        };
+                        let (tse, rows_read) = self.position_error_with_rows(
+                            search_image,
+                            x,
+                            y,
+                            max_tse,
+                            mask,
+                            &comparisons[template_index],
+                        );
+                        // Masked positions are not compared
+                        if let Some(landscape) = landscapes.get_mut(template_index)
+                            && tse != TotalSquaredError::MAX
+                        {
+                            let recorded = match config.error_landscape {
+                                ErrorLandscape::Capped => tse.min(caps[template_index]),
+                                _ => tse,
+                            };
+                            landscape.set(x, y, template_results.tse_to_mse(recorded));
+                        }
+                        statistics.positions_evaluated += 1;
+                        if rows_read < search_image.height {
+                            statistics.positions_rejected_early += 1;
+                            statistics.rows_read_before_rejection += rows_read as u64;
+                        }
+                        let is_match = tse <= template_results.tse_threshold
+                            && max_deviations[template_index].is_none_or(|limit| {
+                                self.max_squared_deviation(
+                                    search_image,
+                                    x,
+                                    y,
+                                    limit,
+                                    config.channels,
+                                ) <= limit
+                            });
+                        if !is_match && template_results.get_matches().is_empty() {
+                            let nearest_distance = nearest[template_index]
+                                .as_ref()
+                                .map_or(TotalSquaredError::MAX, |(distance, _)| *distance);
+                            let candidate = if max_deviations[template_index].is_some() {
+                                let deviation = self.max_squared_deviation(
+                                    search_image,
+                                    x,
+                                    y,
+                                    nearest_distance,
+                                    config.channels,
+                                );
+                                (deviation < nearest_distance).then(|| {
+                                    let (tse, _) = self.position_error_with_rows(
+                                        search_image,
+                                        x,
+                                        y,
+                                        TotalSquaredError::MAX,
+                                        mask,
+                                        &comparisons[template_index],
+                                    );
+                                    (deviation, tse)
+                                })
+                            } else {
+                                // A position rejected by the threshold is compared further only when it may be nearer
+                                let (tse, rows_read) =
+                                    if rows_read < search_image.height && tse < nearest_distance {
+                                        self.position_error_with_rows(
+                                            search_image,
+                                            x,
+                                            y,
+                                            nearest_distance,
+                                            mask,
+                                            &comparisons[template_index],
+                                        )
+                                    } else {
+                                        (tse, rows_read)
+                                    };
+                                (rows_read == search_image.height && tse < nearest_distance)
+                                    .then_some((tse, tse))
+                            };
+                            if let Some((distance, tse)) = candidate {
+                                nearest[template_index] =
+                                    Some((distance, SearchResult { x, y, tse }));
+                            }
+                        }
+                        if is_match {
+                            let previous_threshold = template_results.tse_threshold;
+                            for event in template_results.push(SearchResult { x, y, tse }) {
+                                event_callback(template_index, event);
+                            }
+                            if template_results.tse_threshold < previous_threshold {
+                                statistics.threshold_tightenings.push(ThresholdTightening {
+                                    template_index,
+                                    x,
+                                    y,
+                                    mse: template_results.get_threshold_mse(),
+                                });
+                            }
+                            log::info!(
+                                "{}: pos ({}, {}) ({} pxs)",
+                                template.name,
+                                x,
+                                y,
+                                search_image.width * search_image.height
+                            );
+                        }
+                    }
                 }
             }
         }
 
-        progress_callback(1.0);
+        statistics.add_phase("scan", scan_start);
+
+        progress_callback(1.0);
+
+        let finalize_start = now_ms();
+        for ((template_results, nearest), template_config) in results
+            .iter_mut()
+            .zip(nearest.iter_mut())
+            .zip(&template_configs)
+        {
+            if template_config.threshold_mode == ThresholdMode::Automatic {
+                // Every compared position is a candidate, so the best one is the nearest miss when none is kept
+                *nearest = template_results
+                    .get_matches()
+                    .first()
+                    .map(|best| (best.tse, best.clone()));
+                template_results.apply_auto_threshold(template_config.max_results);
+            }
+        }
+        for (template, template_results) in templates.iter().zip(results.iter_mut()) {
+            template_results.max_squared_deviations = template_results
+                .get_matches()
+                .iter()
+                .map(|m| {
+                    self.max_squared_deviation(
+                        template.image,
+                        m.x,
+                        m.y,
+                        TotalSquaredError::MAX,
+                        config.channels,
+                    ) as f64
+                        / P::DEPTH.full_scale_squared_error()
+                })
+                .collect();
+        }
+        // The nearest position explains why a template has no match
+        for (((template, template_results), comparison), nearest) in templates
+            .iter()
+            .zip(results.iter_mut())
+            .zip(&comparisons)
+            .zip(nearest)
+        {
+            if template_results.get_matches().is_empty()
+                && let Some((_, nearest)) = nearest
+            {
+                let error_map =
+                    self.comparison_error_map(template.image, nearest.x, nearest.y, comparison);
+                template_results.nearest_miss = Some(NearestMiss {
+                    x: nearest.x,
+                    y: nearest.y,
+                    mse: template_results.get_mse(&nearest),
+                    max_squared_deviation: self.max_squared_deviation(
+                        template.image,
+                        nearest.x,
+                        nearest.y,
+                        TotalSquaredError::MAX,
+                        config.channels,
+                    ) as f64
+                        / P::DEPTH.full_scale_squared_error(),
+                    region_mse: error_map.region_mse(NEAREST_MISS_GRID, NEAREST_MISS_GRID),
+                });
+            }
+        }
+        let results = results.into_iter().map(SearchResults::finalize).collect();
+        statistics.add_phase("finalize", finalize_start);
+        Ok(SearchOutcome {
+            results,
+            statistics,
+            error_landscapes: landscapes.into_iter().map(Rc::new).collect(),
+        })
+    }
+}
+
+/// Result of a successful search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchOutcome {
+    // One item per template
+    pub results: Vec<SearchResults>,
+    pub statistics: SearchStatistics,
+    // One item per template when the config asks for them, shared as they can be large
+    pub error_landscapes: Vec<Rc<ErrorMap>>,
+}
+
+/// Diagnostics explaining why a search was fast or slow.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchStatistics {
+    // Pairs of a position and a template
+    pub positions_evaluated: u64,
+    // Positions where the error exceeded the threshold (or the mask was hit) before the last template row
+    pub positions_rejected_early: u64,
+    pub rows_read_before_rejection: u64,
+    pub threshold_tightenings: Vec<ThresholdTightening>,
+    pub phases: Vec<PhaseTiming>,
+}
+
+impl SearchStatistics {
+    pub fn get_average_rows_before_rejection(&self) -> Option<f64> {
+        if self.positions_rejected_early == 0 {
+            None
+        } else {
+            Some(self.rows_read_before_rejection as f64 / self.positions_rejected_early as f64)
+        }
+    }
+    pub fn add_phase(&mut self, name: &str, start_ms: f64) {
+        self.phases.push(PhaseTiming {
+            name: name.to_string(),
+            duration_ms: now_ms() - start_ms,
+        });
+    }
+}
+
+/// The result list got full, so the threshold was lowered to the worst kept result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdTightening {
+    pub template_index: usize,
+    // Position that caused the tightening
+    pub x: u32,
+    pub y: u32,
+    pub mse: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseTiming {
+    pub name: String,
+    pub duration_ms: f64,
+}
 
-        Ok(results.finalize())
-    }
+/// A named image to be searched for, optionally with its own threshold and result cap.
+pub struct Template<'a, P: Subpixel = u8> {
+    pub name: String,
+    pub image: &'a ImageData<P>,
+    // Overrides of the defaults from SearchConfig, see SearchConfig::for_template
+    pub max_mse: Option<f64>,
+    pub max_results: Option<u16>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SearchResult {
     pub x: u32,
     pub y: u32,
     pub tse: TotalSquaredError,
 }
 
-impl SearchResult {
-    pub fn get_mse(&self, squared_errors_divisor: u32) -> f64 {
-        (self.tse as f64) / (squared_errors_divisor as f64) / 65536.0
+/// Number of columns and rows of template parts whose errors are reported by NearestMiss
+pub const NEAREST_MISS_GRID: u32 = 3;
+
+/**
+ * Best position of a template without matches, regardless of the maximum difference.
+ * Its MSE is the smallest maximum difference that would find a match.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearestMiss {
+    pub x: u32,
+    pub y: u32,
+    // By the metric of the search
+    pub mse: f64,
+    // Largest squared error of a single subpixel, normalized like mse
+    pub max_squared_deviation: f64,
+    /**
+     * MSE by the metric of the search of the parts of the template in a NEAREST_MISS_GRID × NEAREST_MISS_GRID grid, row by row.
+     * Similar errors everywhere suggest a color shift, a single bad part an occlusion
+     * and errors growing towards the edges a different scale.
+     */
+    pub region_mse: Vec<f64>,
+}
+
+impl NearestMiss {
+    /// Score of the position in the unit, like SearchResults::format_score
+    pub fn format_score(&self, unit: ThresholdUnit) -> String {
+        match unit {
+            ThresholdUnit::MaxDeviation => unit.format(self.max_squared_deviation),
+            _ => unit.format(self.mse),
+        }
+    }
+
+    /// Rough guess why the template didn't match, based on how the error is spread over its parts
+    pub fn likely_cause(&self) -> Option<&'static str> {
+        let compared: Vec<f64> = self
+            .region_mse
+            .iter()
+            .copied()
+            .filter(|mse| !mse.is_nan())
+            .collect();
+        let max = compared.iter().copied().fold(0.0, f64::max);
+        let min = compared.iter().copied().fold(f64::INFINITY, f64::min);
+        if compared.len() != self.region_mse.len() || max <= 0.0 {
+            return None;
+        }
+        let center = NEAREST_MISS_GRID as usize * NEAREST_MISS_GRID as usize / 2;
+        let edges = compared
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != center)
+            .map(|(_, mse)| *mse)
+            .sum::<f64>()
+            / (compared.len() - 1) as f64;
+        if min >= max / 2.0 {
+            Some("the error is spread evenly, e.g., a color shift or compression")
+        } else if compared.iter().filter(|&&mse| mse >= max / 2.0).count() <= 2 {
+            Some("a small part differs, e.g., an occlusion")
+        } else if edges > 2.0 * compared[center] {
+            Some("the error grows towards the edges, e.g., a different scale")
+        } else {
+            None
+        }
     }
 }
 
-#[derive(Debug)]
+/// Change of SearchResults during the search.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchEvent {
+    Added(SearchResult),
+    // The result is no longer among the best ones
+    Evicted(SearchResult),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SearchResults {
     // We expect about 100 items max => inserting in the first position causes move of cca 1 600 bytes.
     // Not sure if it more or less than allocation overhead caused by tree structures etc, but it is acceptable.
     // Ordered by mse ascending. Not sure if ascending or descending order is better.
     results_ordered: Vec<SearchResult>,
+    template_name: String,
     capacity: u16,
     overflown: bool,
     template_width: u32,
@@ -200,21 +2194,31 @@ pub struct SearchResults {
     main_width: u32,
     main_height: u32,
     squared_error_divisor: u32,
+    // TSE of a full-scale difference of a subpixel, depends on the depth of the searched images
+    full_scale_squared_error: f64,
     tse_threshold: TotalSquaredError,
+    nearest_miss: Option<NearestMiss>, // Only when there is no match
+    auto_threshold: Option<f64>,       // MSE picked when the threshold is automatic
+    // Of each match in results_ordered, empty for results replayed from events
+    max_squared_deviations: Vec<f64>,
 }
 
 impl SearchResults {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        template_name: String,
         capacity: u16,
         template_width: u32,
         template_height: u32,
         main_width: u32,
         main_height: u32,
         squared_error_divisor: u32,
+        full_scale_squared_error: f64,
         tse_threshold: TotalSquaredError,
     ) -> SearchResults {
         SearchResults {
             results_ordered: Vec::with_capacity(capacity as usize),
+            template_name,
             capacity,
             overflown: false,
             template_height,
@@ -222,16 +2226,25 @@ impl SearchResults {
             main_width,
             main_height,
             squared_error_divisor,
+            full_scale_squared_error,
             tse_threshold,
+            nearest_miss: None,
+            auto_threshold: None,
+            max_squared_deviations: Vec::new(),
         }
     }
-    pub fn push(&mut self, result: SearchResult) {
+    /// Returns the changes caused by the result: nothing, addition, or eviction followed by addition.
+    pub fn push(&mut self, result: SearchResult) -> Vec<SearchEvent> {
+        let mut events = Vec::new();
         if self.results_ordered.len() < self.capacity as usize {
+            events.push(SearchEvent::Added(result.clone()));
             self.insert_ordered(result);
         } else {
             self.overflown = true;
             if result.tse < self.results_ordered[self.results_ordered.len() - 1].tse {
-                self.results_ordered.pop();
+                let evicted = self.results_ordered.pop().unwrap();
+                events.push(SearchEvent::Evicted(evicted));
+                events.push(SearchEvent::Added(result.clone()));
                 self.insert_ordered(result);
             } else {
                 // not worth inserting
@@ -241,28 +2254,62 @@ impl SearchResults {
             self.results_ordered.len() <= self.capacity as usize,
             "results_ordered.len() <= self.capacity"
         );
+        events
     }
-    fn insert_ordered(&mut self, result: SearchResult) {
+    /**
+     * Replays an event reported by a running search, so a copy of the results can be displayed live.
+     * Unlike push, it doesn't enforce the capacity, as the evictions are reported as events.
+     */
+    pub fn apply(&mut self, event: SearchEvent) {
+        match event {
+            SearchEvent::Added(result) => self.insert_sorted(result),
+            SearchEvent::Evicted(result) => {
+                self.overflown = true;
+                if let Some(pos) = self.results_ordered.iter().position(|r| *r == result) {
+                    self.results_ordered.remove(pos);
+                }
+            }
+        }
+    }
+    fn insert_sorted(&mut self, result: SearchResult) {
         // find element with higher tse
         match self.results_ordered.iter().position(|r| r.tse > result.tse) {
             Some(pos) => self.results_ordered.insert(pos, result), // insert before the first element with higher tse
             None => self.results_ordered.push(result),
         }
+    }
+    fn insert_ordered(&mut self, result: SearchResult) {
+        self.insert_sorted(result);
         if self.results_ordered.len() == self.capacity as usize {
             // results with the same TSE aren't interesting, so we could go even lower (-1),
             // but we have to handle integer underflow
             self.tse_threshold =
                 TotalSquaredError::saturating_sub(self.results_ordered.last().unwrap().tse, 1);
-            log::info!(
-                "too many items, new TSE threshold: {} => MSE threshold: {}",
-                self.tse_threshold,
-                (self.tse_threshold as f64) / (self.squared_error_divisor as f64) / 65536.0
-            );
         }
     }
     pub fn has_overflown(&self) -> bool {
         self.overflown
     }
+    /// Keeps the candidates up to the automatic threshold, at most capacity of them
+    fn apply_auto_threshold(&mut self, capacity: u16) {
+        let sorted_mse: Vec<f64> = self
+            .results_ordered
+            .iter()
+            .map(|r| self.get_mse(r))
+            .collect();
+        let threshold = auto_threshold(&sorted_mse);
+        self.tse_threshold = mse_to_tse(
+            threshold,
+            self.squared_error_divisor,
+            self.full_scale_squared_error,
+        );
+        let tse_threshold = self.tse_threshold;
+        self.results_ordered.retain(|r| r.tse <= tse_threshold);
+        self.overflown = self.results_ordered.len() > capacity as usize;
+        self.results_ordered.truncate(capacity as usize);
+        self.capacity = capacity;
+        self.auto_threshold = Some(threshold);
+    }
     fn shrink(&mut self) {
         self.results_ordered.shrink_to_fit();
     }
@@ -273,6 +2320,37 @@ impl SearchResults {
     pub fn get_matches(&self) -> &[SearchResult] {
         &self.results_ordered
     }
+    /// MSE normalized to 0..1 (for floats, values above 1.0 can exceed it)
+    pub fn get_mse(&self, result: &SearchResult) -> f64 {
+        self.tse_to_mse(result.tse)
+    }
+    fn tse_to_mse(&self, tse: TotalSquaredError) -> f64 {
+        (tse as f64) / (self.squared_error_divisor as f64) / self.full_scale_squared_error
+    }
+    /// Largest squared error of a single subpixel of the match, normalized like get_mse, None when it is unknown
+    pub fn get_max_squared_deviation(&self, result: &SearchResult) -> Option<f64> {
+        let index = self.results_ordered.iter().position(|r| r == result)?;
+        self.max_squared_deviations.get(index).copied()
+    }
+    /// Score of the match in the unit, in MSE when the maximum deviation is unknown
+    pub fn format_score(&self, result: &SearchResult, unit: ThresholdUnit) -> String {
+        match unit {
+            ThresholdUnit::MaxDeviation => match self.get_max_squared_deviation(result) {
+                Some(deviation) => unit.format(deviation),
+                None => ThresholdUnit::Mse.format(self.get_mse(result)),
+            },
+            _ => unit.format(self.get_mse(result)),
+        }
+    }
+    pub fn get_best_mse(&self) -> Option<f64> {
+        self.results_ordered.first().map(|r| self.get_mse(r))
+    }
+    pub fn get_threshold_mse(&self) -> f64 {
+        self.tse_to_mse(self.tse_threshold)
+    }
+    pub fn get_template_name(&self) -> &str {
+        &self.template_name
+    }
     pub fn get_template_height(&self) -> u32 {
         self.template_height
     }
@@ -288,6 +2366,14 @@ impl SearchResults {
     pub fn get_squared_errors_divisor(&self) -> u32 {
         self.squared_error_divisor
     }
+    /// The maximum difference picked for this template and main image, None unless the threshold is automatic
+    pub fn get_auto_threshold(&self) -> Option<f64> {
+        self.auto_threshold
+    }
+    /// The best position when the search found no match, None when it has matches or no position was compared
+    pub fn get_nearest_miss(&self) -> Option<&NearestMiss> {
+        self.nearest_miss.as_ref()
+    }
 }
 
 #[cfg(test)]
@@ -341,4 +2427,1009 @@ mod tests {
             assert_eq!(subpixel_squared_error(i, i + 3), 9);
         }
     }
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn solid_image(width: u32, height: u32, rgba: [u8; 4]) -> ImageData {
+        ImageData {
+            width,
+            height,
+            pixels: rgba.repeat((width * height) as usize),
+        }
+    }
+
+    fn encode_png<I: ::image::ImageEncoder>(
+        encoder: I,
+        width: u32,
+        height: u32,
+        bytes: &[u8],
+        color: ::image::ExtendedColorType,
+    ) {
+        encoder.write_image(bytes, width, height, color).unwrap();
+    }
+
+    #[test]
+    fn test_decode_is_bit_exact() {
+        // Semi-transparent pixels would be changed by premultiplied alpha in a canvas
+        let pixels = vec![
+            10, 20, 30, 1, 200, 100, 50, 127, 255, 255, 255, 0, 0, 0, 0, 255,
+        ];
+        let mut png = Vec::new();
+        encode_png(
+            ::image::codecs::png::PngEncoder::new(&mut png),
+            2,
+            2,
+            &pixels,
+            ::image::ExtendedColorType::Rgba8,
+        );
+        let decoded = DecodedImage::decode("test.png", &png, true).unwrap();
+        assert_eq!(decoded.depth(), Depth::U8);
+        let image_data = decoded.to_image_data::<u8>().unwrap();
+        assert_eq!((image_data.width, image_data.height), (2, 2));
+        assert_eq!(image_data.pixels, pixels);
+        assert!(matches!(
+            DecodedImage::decode("broken.png", &png[..20], true),
+            Err(Error::Decode { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_16_bit() {
+        let pixels: Vec<u16> = vec![1, 2, 3, 65535, 1000, 2000, 3000, 32768];
+        let bytes: Vec<u8> = pixels.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let mut png = Vec::new();
+        encode_png(
+            ::image::codecs::png::PngEncoder::new(&mut png),
+            2,
+            1,
+            &bytes,
+            ::image::ExtendedColorType::Rgba16,
+        );
+        let decoded = DecodedImage::decode("test.png", &png, true).unwrap();
+        assert_eq!(decoded.depth(), Depth::U16);
+        assert_eq!(decoded.to_image_data::<u16>().unwrap().pixels, pixels);
+        // Values differing only in the low byte would be equal after truncating to 8 bits
+        assert_eq!(
+            decoded.to_image_data::<u8>().unwrap().pixels[..3],
+            [0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_decode_applies_metadata() {
+        use ::image::ImageEncoder;
+        // TIFF structure of EXIF with a single entry: orientation 6, i.e., rotated 90° clockwise
+        let exif = vec![
+            0x49, 0x49, 0x2a, 0, 8, 0, 0, 0, 1, 0, 0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0, 0, 0,
+            0, 0,
+        ];
+        let pixels = vec![100, 150, 200, 255, 0, 0, 0, 255];
+        let mut png = Vec::new();
+        let mut encoder = ::image::codecs::png::PngEncoder::new(&mut png);
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder
+            .set_icc_profile(moxcms::ColorProfile::new_display_p3().encode().unwrap())
+            .unwrap();
+        encode_png(encoder, 2, 1, &pixels, ::image::ExtendedColorType::Rgba8);
+
+        let metadata = DecodedImage::read_metadata("photo.png", &png).unwrap();
+        assert_eq!(metadata.orientation, Orientation::Rotate90);
+        assert!(metadata.color_profile.is_some());
+
+        let raw = DecodedImage::decode("photo.png", &png, false).unwrap();
+        assert_eq!(raw.metadata(), &metadata);
+        assert_eq!(raw.to_image_data::<u8>().unwrap().pixels, pixels);
+
+        let applied = DecodedImage::decode("photo.png", &png, true)
+            .unwrap()
+            .to_image_data::<u8>()
+            .unwrap();
+        assert_eq!((applied.width, applied.height), (1, 2));
+        // Rotated clockwise, the left pixel is at the top; Display P3 colors are more saturated than sRGB ones
+        assert_ne!(applied.pixels[..4], pixels[..4]);
+        assert_eq!(applied.pixels[3], 255);
+        assert_eq!(applied.pixels[4..], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_decode_frames() {
+        let colors: [[u8; 4]; 3] = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let mut gif = Vec::new();
+        ::image::codecs::gif::GifEncoder::new(&mut gif)
+            .encode_frames(colors.iter().map(|&color| {
+                ::image::Frame::new(::image::RgbaImage::from_pixel(2, 2, ::image::Rgba(color)))
+            }))
+            .unwrap();
+        let frames = DecodedImage::decode_frames("animation.gif", &gif, true).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[1].to_image_data::<u8>().unwrap().pixels,
+            colors[1].repeat(4)
+        );
+
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut tiff).unwrap();
+        for color in &colors[..2] {
+            encoder
+                .write_image::<tiff::encoder::colortype::RGBA8>(1, 2, &color.repeat(2))
+                .unwrap();
+        }
+        let pages = DecodedImage::decode_frames("pages.tiff", tiff.get_ref(), true).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(
+            pages[1].to_image_data::<u8>().unwrap().pixels,
+            colors[1].repeat(2)
+        );
+
+        // Still images have a single frame
+        let mut png = Vec::new();
+        encode_png(
+            ::image::codecs::png::PngEncoder::new(&mut png),
+            1,
+            1,
+            &colors[2],
+            ::image::ExtendedColorType::Rgba8,
+        );
+        assert_eq!(
+            DecodedImage::decode_frames("still.png", &png, true)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_template_cropped_from_main_image() {
+        // Two identical buttons on a gradient
+        let mut image = ::image::RgbaImage::from_fn(12, 6, |x, y| {
+            ::image::Rgba([x as u8 * 20, y as u8 * 40, 0, 255])
+        });
+        for (bx, by) in [(1, 1), (8, 3)] {
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                image.put_pixel(
+                    bx + dx,
+                    by + dy,
+                    ::image::Rgba([255, 255, 255, dx as u8 * 100]),
+                );
+            }
+        }
+        let main = DecodedImage {
+            image: ::image::DynamicImage::ImageRgba8(image),
+            metadata: ImageMetadata::default(),
+        };
+        let source = rect(1, 1, 2, 2);
+        let cropped = main.crop(&source).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (2, 2));
+        assert!(main.crop(&rect(11, 0, 2, 2)).is_err());
+
+        let templates = [DecodedTemplate {
+            name: source.to_string(),
+            image: &cropped,
+            max_mse: None,
+            max_results: None,
+        }];
+        let config = SearchConfig {
+            template_region: Some(source),
+            ..exact(10)
+        };
+        let mask = Mask::from_parts(12, 6, None, &config.excluded_regions_in(0, 0)).unwrap();
+        let outcome = block_on(main.find_subimage(
+            &templates,
+            &config,
+            |_| {},
+            |_, _| {},
+            Some(&mask),
+            &CancellationToken::default(),
+        ))
+        .unwrap();
+        // Only the other occurrence is found
+        assert_eq!(
+            outcome.results[0].get_matches(),
+            &[SearchResult { x: 8, y: 3, tse: 0 }]
+        );
+    }
+
+    #[test]
+    fn test_subpixel_squared_error_depths() {
+        assert_eq!(u16::squared_error(0, 65535), 65535 * 65535);
+        assert_eq!(u16::to_total_squared_error(u16::squared_error(7, 5)), 4);
+        assert_eq!(
+            f32::to_total_squared_error(f32::squared_error(0.5, 0.25)),
+            1 << 28
+        );
+        assert_eq!(
+            f32::to_total_squared_error(f32::squared_error(f32::NAN, 0.0)),
+            TotalSquaredError::MAX
+        );
+        assert_eq!(
+            f32::to_total_squared_error(f32::squared_error(1e30, 0.0)),
+            TotalSquaredError::MAX
+        );
+    }
+
+    #[test]
+    fn test_find_subimage_in_search_depth() {
+        // Only the low bytes differ from the surroundings, so 8-bit search couldn't tell the positions apart
+        let pixels: Vec<u16> = (0..8 * 6)
+            .flat_map(|i| [0x1200 + i as u16 * 3, 0x3400, 0x5600, 0xffff])
+            .collect();
+        let main = DecodedImage {
+            image: ::image::DynamicImage::ImageRgba16(
+                ::image::ImageBuffer::from_raw(8, 6, pixels).unwrap(),
+            ),
+            metadata: ImageMetadata::default(),
+        };
+        let searched = DecodedImage {
+            image: main.image.crop_imm(3, 2, 2, 2),
+            metadata: ImageMetadata::default(),
+        };
+        let templates = [DecodedTemplate {
+            name: "t".to_string(),
+            image: &searched,
+            max_mse: None,
+            max_results: None,
+        }];
+        assert_eq!(main.search_depth(&templates), Depth::U16);
+        let outcome = block_on(main.find_subimage(
+            &templates,
+            &exact(3),
+            |_| {},
+            |_, _| {},
+            None,
+            &CancellationToken::default(),
+        ))
+        .unwrap();
+        assert_eq!(
+            outcome.results[0].get_matches(),
+            &[SearchResult { x: 3, y: 2, tse: 0 }]
+        );
+        assert_eq!(outcome.statistics.phases[0].name, "convert to U16");
+        // The same template in floats
+        let float_main = DecodedImage {
+            image: ::image::DynamicImage::ImageRgba32F(main.image.to_rgba32f()),
+            metadata: ImageMetadata::default(),
+        };
+        assert_eq!(float_main.search_depth(&templates), Depth::F32);
+        let results = float_main.new_search_results(&templates, &exact(3));
+        assert_eq!(results[0].get_threshold_mse(), 0.0);
+    }
+
+    #[test]
+    fn test_mask_from_rects() {
+        let mask = Mask::from_parts(10, 5, None, &[rect(2, 1, 3, 2)]).unwrap();
+        assert!(!mask.overlaps_row(0, 0, 10));
+        assert!(mask.overlaps_row(0, 1, 3));
+        assert!(!mask.overlaps_row(0, 1, 2));
+        assert!(!mask.overlaps_row(5, 2, 5));
+        assert!(mask.overlaps_row(4, 2, 1));
+        assert!(!mask.overlaps_row(0, 3, 10));
+    }
+
+    #[test]
+    fn test_mask_from_image() {
+        let black = solid_image(3, 1, [0, 0, 0, 255]);
+        assert!(
+            Mask::from_parts(3, 1, Some(&black), &[])
+                .unwrap()
+                .overlaps_row(1, 0, 1)
+        );
+        let white = solid_image(3, 1, [255, 255, 255, 255]);
+        assert!(
+            !Mask::from_parts(3, 1, Some(&white), &[])
+                .unwrap()
+                .overlaps_row(0, 0, 3)
+        );
+        let transparent = solid_image(3, 1, [0, 0, 0, 0]);
+        assert!(
+            !Mask::from_parts(3, 1, Some(&transparent), &[])
+                .unwrap()
+                .overlaps_row(0, 0, 3)
+        );
+        assert!(Mask::from_parts(4, 1, Some(&black), &[]).is_err());
+    }
+
+    #[test]
+    fn test_total_squared_error_masked() {
+        let main = solid_image(4, 4, [10, 10, 10, 255]);
+        let search = solid_image(2, 2, [10, 10, 10, 255]);
+        let mask = Mask::from_parts(4, 4, None, &[rect(3, 3, 1, 1)]).unwrap();
+        assert_eq!(
+            main.total_squared_error(&search, 0, 0, 0, Some(&mask), Channels::Rgba),
+            0
+        );
+        assert_eq!(
+            main.total_squared_error(&search, 2, 2, 0, Some(&mask), Channels::Rgba),
+            TotalSquaredError::MAX
+        );
+    }
+
+    #[test]
+    fn test_error_map() {
+        let mut main = solid_image(4, 3, [10, 10, 10, 255]);
+        // A single pixel differs in red and alpha
+        main.pixels[(4 + 2) * 4] = 30;
+        main.pixels[(4 + 2) * 4 + 3] = 0;
+        let search = solid_image(2, 2, [10, 10, 10, 255]);
+        let map = main.error_map(&search, 1, 0, Channels::Rgba);
+        assert_eq!((map.get_width(), map.get_height()), (2, 2));
+        assert_eq!(map.get(0, 0), 0.0);
+        assert_eq!(map.get(1, 1), (400.0 + 65025.0) / 4.0 / 65536.0);
+        assert_eq!(map.get_max(), map.get(1, 1));
+        // The mean is the MSE of the match
+        let tse =
+            main.total_squared_error(&search, 1, 0, TotalSquaredError::MAX, None, Channels::Rgba);
+        assert_eq!(map.get_mse(), tse as f64 / 16.0 / 65536.0);
+        let rgb = main.error_map(&search, 1, 0, Channels::Rgb);
+        assert_eq!(rgb.get(1, 1), 400.0 / 3.0 / 65536.0);
+
+        let heatmap = map.to_heatmap().image.to_rgba8();
+        assert_eq!(heatmap.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(heatmap.get_pixel(1, 1).0, [255, 255, 255, 255]);
+    }
+
+    // The search yields only in the browser, so the future completes on the first poll
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        match std::pin::pin!(future).poll(&mut context) {
+            std::task::Poll::Ready(output) => output,
+            std::task::Poll::Pending => panic!("search future is pending"),
+        }
+    }
+
+    fn gradient_image(width: u32, height: u32) -> ImageData {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).flat_map(move |x| [(x * 7) as u8, (y * 13) as u8, 0, 255]))
+            .collect();
+        ImageData::new(width, height, pixels).unwrap()
+    }
+
+    // Unlike in a gradient, shifted positions of a template are as bad as any other
+    fn noise_image(width: u32, height: u32) -> ImageData {
+        let pixels = (0..height)
+            .flat_map(|y| {
+                (0..width).flat_map(move |x| {
+                    // Mixed, so the values of nearby pixels are unrelated
+                    let mut hash = (y * width + x).wrapping_mul(0x9E3779B9);
+                    hash ^= hash >> 15;
+                    hash = hash.wrapping_mul(0x85EBCA6B);
+                    hash ^= hash >> 13;
+                    [(hash >> 24) as u8, 0, 0, 255]
+                })
+            })
+            .collect();
+        ImageData::new(width, height, pixels).unwrap()
+    }
+
+    fn crop(image: &ImageData, rect: Rect) -> ImageData {
+        let pixels = (rect.y..rect.y + rect.height)
+            .flat_map(|y| image.get_pixels(rect.x, y, rect.width as usize).to_vec())
+            .collect();
+        ImageData::new(rect.width, rect.height, pixels).unwrap()
+    }
+
+    fn template<'a>(name: &str, image: &'a ImageData) -> Template<'a> {
+        Template {
+            name: name.to_string(),
+            image,
+            max_mse: None,
+            max_results: None,
+        }
+    }
+
+    fn exact(max_results: u16) -> SearchConfig {
+        SearchConfig::builder()
+            .max_mse(0.0)
+            .max_results(max_results)
+            .build()
+            .unwrap()
+    }
+
+    fn search(
+        main: &ImageData,
+        templates: &[Template],
+        config: &SearchConfig,
+    ) -> Result<SearchOutcome, Error> {
+        block_on(main.find_subimage(
+            templates,
+            config,
+            |_| {},
+            |_, _| {},
+            None,
+            &CancellationToken::default(),
+        ))
+    }
+
+    #[test]
+    fn test_find_subimage_more_templates() {
+        let main = gradient_image(20, 15);
+        let first = crop(&main, rect(3, 4, 5, 3));
+        let second = crop(&main, rect(12, 9, 2, 6));
+        let templates = [template("first", &first), template("second", &second)];
+        let results = search(&main, &templates, &exact(5)).unwrap().results;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].get_template_name(), "first");
+        assert_eq!(
+            results[0].get_matches(),
+            &[SearchResult { x: 3, y: 4, tse: 0 }]
+        );
+        assert_eq!(
+            results[1].get_matches(),
+            &[SearchResult {
+                x: 12,
+                y: 9,
+                tse: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_subimage_template_limits() {
+        let main = gradient_image(20, 15);
+        let searched = crop(&main, rect(3, 4, 5, 3));
+        let templates = [
+            Template {
+                max_results: Some(2),
+                ..template("capped", &searched)
+            },
+            template("default", &searched),
+            Template {
+                max_mse: Some(0.0),
+                ..template("exact", &searched)
+            },
+        ];
+        let config = SearchConfig::builder()
+            .max_mse(0.5)
+            .max_results(5)
+            .build()
+            .unwrap();
+        let results = search(&main, &templates, &config).unwrap().results;
+        // The config supplies the limits a template doesn't have of its own
+        assert_eq!(results[0].get_matches().len(), 2);
+        assert_eq!(results[1].get_matches().len(), 5);
+        assert_eq!(
+            results[2].get_matches(),
+            &[SearchResult { x: 3, y: 4, tse: 0 }]
+        );
+        let invalid = [Template {
+            max_results: Some(0),
+            ..template("t", &searched)
+        }];
+        assert!(search(&main, &invalid, &config).is_err());
+    }
+
+    #[test]
+    fn test_find_subimage_regions() {
+        let main = gradient_image(20, 15);
+        let searched = crop(&main, rect(3, 4, 5, 3));
+        let templates = [template("t", &searched)];
+        let outside = SearchConfig {
+            regions: vec![rect(5, 0, 10, 10)],
+            ..exact(5)
+        };
+        let results = search(&main, &templates, &outside).unwrap().results;
+        assert!(results[0].get_matches().is_empty());
+        let inside = SearchConfig {
+            regions: vec![rect(0, 4, 4, 1)],
+            ..exact(5)
+        };
+        let results = search(&main, &templates, &inside).unwrap().results;
+        assert_eq!(results[0].get_matches().len(), 1);
+    }
+
+    #[test]
+    fn test_find_subimage_error_landscape() {
+        let main = gradient_image(20, 15);
+        let searched = crop(&main, rect(3, 4, 5, 3));
+        let templates = [template("t", &searched)];
+        let outcome = search(&main, &templates, &exact(5)).unwrap();
+        assert!(outcome.error_landscapes.is_empty());
+
+        let config = SearchConfig {
+            max_mse: 0.001,
+            regions: vec![rect(0, 0, 10, 10)],
+            error_landscape: ErrorLandscape::Exact,
+            ..exact(5)
+        };
+        let outcome = search(&main, &templates, &config).unwrap();
+        let exact_map = &outcome.error_landscapes[0];
+        assert_eq!((exact_map.get_width(), exact_map.get_height()), (16, 13));
+        assert_eq!(exact_map.get(3, 4), 0.0);
+        let expected = main.total_squared_error(
+            &searched,
+            9,
+            9,
+            TotalSquaredError::MAX,
+            None,
+            Channels::Rgba,
+        );
+        assert_eq!(
+            exact_map.get(9, 9),
+            outcome.results[0].tse_to_mse(expected) as f32
+        );
+        // Outside of the search regions
+        assert!(exact_map.get(12, 3).is_nan());
+
+        let capped = SearchConfig {
+            error_landscape: ErrorLandscape::Capped,
+            ..config
+        };
+        let outcome = search(&main, &templates, &capped).unwrap();
+        let capped_map = &outcome.error_landscapes[0];
+        assert_eq!(capped_map.get(3, 4), 0.0);
+        // Errors above the maximum MSE are recorded as the maximum MSE
+        assert!(exact_map.get(9, 9) > capped_map.get(9, 9));
+        assert!((capped_map.get(9, 9) - 0.001).abs() < 1e-5);
+        assert_eq!(capped_map.get_max(), capped_map.get(9, 9));
+        assert_eq!(capped_map.to_raw_f32().len(), 16 * 13 * 4);
+        assert!(capped_map.to_gray16_png().is_ok());
+    }
+
+    #[test]
+    fn test_find_subimage_nearest_miss() {
+        // Noise, so shifted positions are far worse than the occluded one
+        let main = noise_image(20, 15);
+        let mut searched = crop(&main, rect(6, 3, 6, 6));
+        // A small occlusion in the bottom-right part of the template
+        for (x, y) in [(4, 4), (5, 4), (4, 5), (5, 5)] {
+            let index = ((y * 6 + x) * 4) as usize;
+            searched.pixels[index] ^= 32;
+        }
+        let templates = [template("t", &searched)];
+        let outcome = search(&main, &templates, &exact(5)).unwrap();
+        assert!(outcome.results[0].get_matches().is_empty());
+        let miss = outcome.results[0].get_nearest_miss().unwrap();
+        assert_eq!((miss.x, miss.y), (6, 3));
+        let expected = main.total_squared_error(
+            &searched,
+            6,
+            3,
+            TotalSquaredError::MAX,
+            None,
+            Channels::Rgba,
+        );
+        assert_eq!(miss.mse, outcome.results[0].tse_to_mse(expected));
+        assert_eq!(miss.region_mse.len(), 9);
+        assert!(miss.region_mse[..8].iter().all(|&mse| mse == 0.0));
+        assert!(miss.region_mse[8] > 0.0);
+        assert_eq!(
+            miss.likely_cause(),
+            Some("a small part differs, e.g., an occlusion")
+        );
+
+        // Its MSE is the maximum difference that finds it
+        let config = SearchConfig {
+            max_mse: miss.mse,
+            ..exact(5)
+        };
+        let outcome = search(&main, &templates, &config).unwrap();
+        assert_eq!(outcome.results[0].get_matches()[0].x, 6);
+        assert_eq!(outcome.results[0].get_nearest_miss(), None);
+
+        // The parts are scored by the metric of the search, here the fraction of differing pixels
+        let config = SearchConfig {
+            metric: Metric::MatchingPixels,
+            ..exact(5)
+        };
+        let outcome = search(&main, &templates, &config).unwrap();
+        let miss = outcome.results[0].get_nearest_miss().unwrap();
+        assert_eq!((miss.x, miss.y), (6, 3));
+        assert_eq!(miss.mse, 4.0 / 36.0);
+        assert!(miss.region_mse[..8].iter().all(|&mse| mse == 0.0));
+        assert_eq!(miss.region_mse[8], 1.0);
+    }
+
+    #[test]
+    fn test_auto_threshold() {
+        assert_eq!(auto_threshold(&[]), 0.0);
+        assert_eq!(auto_threshold(&[0.01]), 0.0);
+        // No clear gap, e.g., the template doesn't occur
+        assert_eq!(auto_threshold(&[0.05, 0.06, 0.08, 0.1]), 0.0);
+        // Exact matches and the background
+        let threshold = auto_threshold(&[0.0, 0.0, 0.05, 0.06]);
+        assert!(threshold > 0.0 && threshold < 0.05, "{}", threshold);
+        // The largest gap wins over an earlier smaller one
+        let threshold = auto_threshold(&[0.001, 0.003, 0.004, 0.1]);
+        assert!(threshold > 0.004 && threshold < 0.1, "{}", threshold);
+    }
+
+    #[test]
+    fn test_find_subimage_auto_threshold() {
+        let mut main = noise_image(30, 20);
+        let searched = crop(&main, rect(3, 4, 5, 3));
+        // A second, slightly different occurrence
+        for y in 0..3 {
+            let row = searched.get_pixels(0, y, 5).to_vec();
+            let start = (((12 + y) * 30 + 20) * 4) as usize;
+            main.pixels[start..start + row.len()].copy_from_slice(&row);
+        }
+        main.pixels[((12 * 30 + 20) * 4) as usize] ^= 4;
+        let config = SearchConfig::builder()
+            .max_mse(0.0)
+            .threshold_mode(ThresholdMode::Automatic)
+            .max_results(5)
+            .build()
+            .unwrap();
+        let templates = [template("t", &searched)];
+        let outcome = search(&main, &templates, &config).unwrap();
+        let results = &outcome.results[0];
+        let positions: Vec<(u32, u32)> = results.get_matches().iter().map(|m| (m.x, m.y)).collect();
+        assert_eq!(positions, [(3, 4), (20, 12)]);
+        assert!(!results.has_overflown());
+        // The picked threshold finds the same matches when it is pinned
+        let threshold = results.get_auto_threshold().unwrap();
+        assert!(threshold >= results.get_mse(&results.get_matches()[1]));
+        let pinned = SearchConfig {
+            max_mse: threshold,
+            threshold_mode: ThresholdMode::Fixed,
+            ..config
+        };
+        let outcome = search(&main, &templates, &pinned).unwrap();
+        assert_eq!(outcome.results[0].get_matches(), results.get_matches());
+        assert_eq!(outcome.results[0].get_auto_threshold(), None);
+    }
+
+    #[test]
+    fn test_find_subimage_best_k() {
+        let main = gradient_image(8, 6);
+        let searched = crop(&main, rect(1, 2, 5, 3));
+        let templates = [template("t", &searched)];
+        let config = SearchConfig::builder()
+            .max_mse(0.0)
+            .threshold_mode(ThresholdMode::BestK)
+            .max_results(3)
+            .build()
+            .unwrap();
+        // The best positions are returned even though only one of them is within max_mse
+        let results = &search(&main, &templates, &config).unwrap().results[0];
+        assert_eq!(results.get_matches().len(), 3);
+        assert_eq!(
+            results.get_matches()[0],
+            SearchResult { x: 1, y: 2, tse: 0 }
+        );
+        assert!(results.get_matches()[1].tse <= results.get_matches()[2].tse);
+
+        // Masked positions are never returned, even with room for more results
+        let mask = Mask::from_parts(8, 6, None, &[rect(7, 0, 1, 6)]).unwrap();
+        let outcome = block_on(main.find_subimage(
+            &templates,
+            &SearchConfig {
+                max_results: 20,
+                ..config
+            },
+            |_| {},
+            |_, _| {},
+            Some(&mask),
+            &CancellationToken::default(),
+        ))
+        .unwrap();
+        let matches = outcome.results[0].get_matches();
+        assert_eq!(matches.len(), 3 * 4);
+        assert!(matches.iter().all(|m| m.x < 3));
+    }
+
+    #[test]
+    fn test_find_subimage_max_deviation() {
+        let main = gradient_image(20, 15);
+        let mut searched = crop(&main, rect(3, 4, 5, 3));
+        // A single subpixel differs by 3 levels, so the MSE is small
+        searched.pixels[0] += 3;
+        let templates = [template("t", &searched)];
+        let config = |levels: f64| {
+            SearchConfig::builder()
+                .threshold_unit(ThresholdUnit::MaxDeviation)
+                .max_mse(ThresholdUnit::MaxDeviation.to_mse(levels))
+                .build()
+                .unwrap()
+        };
+        let outcome = search(&main, &templates, &config(2.0)).unwrap();
+        assert!(outcome.results[0].get_matches().is_empty());
+        // The same MSE is accepted without the deviation limit
+        let mse = SearchConfig {
+            threshold_unit: ThresholdUnit::Mse,
+            ..config(2.0)
+        };
+        assert_eq!(
+            search(&main, &templates, &mse).unwrap().results[0]
+                .get_matches()
+                .len(),
+            1
+        );
+
+        let outcome = search(&main, &templates, &config(3.0)).unwrap();
+        let results = &outcome.results[0];
+        let m = &results.get_matches()[0];
+        assert_eq!((m.x, m.y), (3, 4));
+        assert_eq!(results.get_max_squared_deviation(m), Some(9.0 / 65536.0));
+        assert_eq!(
+            results.format_score(m, ThresholdUnit::MaxDeviation),
+            "3.00 levels"
+        );
+    }
+
+    #[test]
+    fn test_find_subimage_nearest_miss_by_deviation() {
+        let mut main = solid_image(20, 8, [0, 0, 0, 255]);
+        let mut fill = |x0: u32, y0: u32, rgba: [u8; 4]| {
+            for y in y0..y0 + 4 {
+                for x in x0..x0 + 4 {
+                    let index = ((y * 20 + x) * 4) as usize;
+                    main.pixels[index..index + 4].copy_from_slice(&rgba);
+                }
+            }
+        };
+        // One subpixel differs a lot from the template at (2, 2), every color a little at (12, 2)
+        fill(2, 2, [100, 100, 100, 255]);
+        fill(12, 2, [80, 80, 80, 255]);
+        main.pixels[(2 * 20 + 2) * 4] = 0;
+        let searched = solid_image(4, 4, [100, 100, 100, 255]);
+        let templates = [template("t", &searched)];
+        let config = SearchConfig::builder()
+            .threshold_unit(ThresholdUnit::MaxDeviation)
+            .max_mse(ThresholdUnit::MaxDeviation.to_mse(10.0))
+            .build()
+            .unwrap();
+
+        let outcome = search(&main, &templates, &config).unwrap();
+        let miss = outcome.results[0].get_nearest_miss().unwrap();
+        assert_eq!((miss.x, miss.y), (12, 2));
+        assert_eq!(
+            miss.format_score(ThresholdUnit::MaxDeviation),
+            "20.00 levels"
+        );
+
+        // The same threshold as an MSE finds the position with the smaller error
+        let mse = SearchConfig {
+            threshold_unit: ThresholdUnit::Mse,
+            ..config
+        };
+        let outcome = search(&main, &templates, &mse).unwrap();
+        let miss = outcome.results[0].get_nearest_miss().unwrap();
+        assert_eq!((miss.x, miss.y), (2, 2));
+    }
+
+    #[test]
+    fn test_find_subimage_matching_pixels() {
+        let main = noise_image(20, 15);
+        let mut searched = crop(&main, rect(6, 3, 5, 4));
+        // A cursor over 2 of the 20 pixels, the rest differs a little
+        for (i, red) in searched.pixels.iter_mut().step_by(4).enumerate() {
+            *red = match i {
+                0 | 1 => red.wrapping_add(128),
+                _ => red.saturating_add(1),
+            };
+        }
+        let templates = [template("t", &searched)];
+        // The cursor alone makes an MSE of 0.625%
+        let mse = SearchConfig::builder().max_mse(0.005).build().unwrap();
+        assert!(
+            search(&main, &templates, &mse).unwrap().results[0]
+                .get_matches()
+                .is_empty()
+        );
+
+        let config = SearchConfig {
+            metric: Metric::MatchingPixels,
+            max_mse: 0.1,
+            ..mse
+        };
+        let outcome = search(&main, &templates, &config).unwrap();
+        let results = &outcome.results[0];
+        assert_eq!(results.get_matches().len(), 1);
+        let m = &results.get_matches()[0];
+        assert_eq!((m.x, m.y), (6, 3));
+        // The score is the fraction of differing pixels
+        assert_eq!(results.get_mse(m), 2.0 / 20.0);
+        let stricter = SearchConfig {
+            max_mse: 0.05,
+            ..config
+        };
+        assert!(
+            search(&main, &templates, &stricter).unwrap().results[0]
+                .get_matches()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_find_subimage_normalized() {
+        let main = noise_image(20, 15);
+        let mut searched = crop(&main, rect(6, 3, 5, 4));
+        // Darker and with less contrast, like in another theme
+        for pixel in searched.pixels.chunks_exact_mut(4) {
+            for subpixel in &mut pixel[..3] {
+                *subpixel = (*subpixel as f64 * 0.5 + 60.0).round() as u8;
+            }
+        }
+        let templates = [template("t", &searched)];
+        let mse = SearchConfig::builder().max_mse(0.01).build().unwrap();
+        assert!(
+            search(&main, &templates, &mse).unwrap().results[0]
+                .get_matches()
+                .is_empty()
+        );
+
+        for metric in [Metric::NormalizedChannels, Metric::NormalizedLuminance] {
+            let config = SearchConfig {
+                metric,
+                ..mse.clone()
+            };
+            let outcome = search(&main, &templates, &config).unwrap();
+            let results = &outcome.results[0];
+            assert_eq!(results.get_matches().len(), 1, "{:?}", metric);
+            let m = &results.get_matches()[0];
+            assert_eq!((m.x, m.y), (6, 3));
+            // Only the rounding of the darker values differs
+            assert!(results.get_mse(m) < 0.001, "{:?}", metric);
+        }
+    }
+
+    #[test]
+    fn test_integral_image() {
+        let image = noise_image(7, 5);
+        let windows = IntegralImage::new(&image, false, Channels::Rgba);
+        let (mean, inverse_deviation) = windows.window_scales(2, 1, 4, 3)[0];
+        let reds: Vec<f64> = (1..4)
+            .flat_map(|y| (2..6).map(move |x| (x, y)))
+            .map(|(x, y)| image.get_pixels(x, y, 1)[0] as f64)
+            .collect();
+        let expected_mean = reds.iter().sum::<f64>() / 12.0;
+        let expected_variance = reds
+            .iter()
+            .map(|r| (r - expected_mean).powi(2))
+            .sum::<f64>()
+            / 12.0;
+        assert!((mean - expected_mean).abs() < 1e-9);
+        assert!((inverse_deviation - 1.0 / expected_variance.sqrt()).abs() < 1e-9);
+        // Blue is 0 and alpha 255 everywhere, flat windows are only centered
+        assert_eq!(windows.window_scales(2, 1, 4, 3)[2], (0.0, 0.0));
+        assert_eq!(windows.window_scales(0, 0, 7, 5)[3], (255.0, 0.0));
+    }
+
+    #[test]
+    fn test_integral_image_large_flat_window() {
+        // Bright, so the sums of squares are large, and luminance values, which are not integers
+        let image = solid_image(400, 300, [251, 248, 244, 255]);
+        for luminance in [false, true] {
+            let windows = IntegralImage::new(&image, luminance, Channels::Rgba);
+            for (x, y) in [(0, 0), (200, 150), (133, 75), (1, 150)] {
+                let (mean, inverse_deviation) = windows.window_scales(x, y, 400 - x, 300 - y)[0];
+                assert!(mean > 240.0, "{} at ({}, {})", mean, x, y);
+                assert_eq!(inverse_deviation, 0.0, "{} at ({}, {})", luminance, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_subimage_channels() {
+        let main = gradient_image(20, 15);
+        let mut searched = crop(&main, rect(3, 4, 5, 3));
+        // Alpha differs everywhere, the colors match
+        for alpha in searched.pixels.iter_mut().skip(3).step_by(4) {
+            *alpha = 0;
+        }
+        let templates = [template("t", &searched)];
+        let results = search(&main, &templates, &exact(1)).unwrap().results;
+        assert!(results[0].get_matches().is_empty());
+        let rgb = SearchConfig {
+            channels: Channels::Rgb,
+            ..exact(1)
+        };
+        let results = search(&main, &templates, &rgb).unwrap().results;
+        assert_eq!(
+            results[0].get_matches(),
+            &[SearchResult { x: 3, y: 4, tse: 0 }]
+        );
+        assert_eq!(results[0].get_squared_errors_divisor(), 5 * 3 * 3);
+    }
+
+    #[test]
+    fn test_find_subimage_errors() {
+        let main = gradient_image(20, 15);
+        let large = gradient_image(21, 2);
+        let small = gradient_image(2, 2);
+        assert!(matches!(
+            search(&main, &[template("t", &large)], &exact(1)),
+            Err(Error::TemplateLargerThanImage { .. })
+        ));
+        let invalid = SearchConfig {
+            max_mse: 1.5,
+            ..exact(1)
+        };
+        assert!(matches!(
+            search(&main, &[template("t", &small)], &invalid),
+            Err(Error::InvalidParameter { .. })
+        ));
+        let cancelled = CancellationToken::default();
+        cancelled.cancel();
+        assert_eq!(
+            block_on(main.find_subimage(
+                &[template("t", &small)],
+                &exact(1),
+                |_| {},
+                |_, _| {},
+                None,
+                &cancelled
+            )),
+            Err(Error::Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_search_statistics() {
+        let main = gradient_image(20, 15);
+        let searched = crop(&main, rect(3, 4, 5, 3));
+        let statistics = search(&main, &[template("t", &searched)], &exact(1))
+            .unwrap()
+            .statistics;
+        assert_eq!(statistics.positions_evaluated, 16 * 13);
+        // Exact match is required, so every other position is rejected on the first row
+        assert_eq!(statistics.positions_rejected_early, 16 * 13 - 1);
+        assert_eq!(statistics.get_average_rows_before_rejection(), Some(1.0));
+        // Threshold 0 cannot get any lower
+        assert!(statistics.threshold_tightenings.is_empty());
+        let phases: Vec<&str> = statistics.phases.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(phases, ["setup", "scan", "finalize"]);
+    }
+
+    #[test]
+    fn test_search_events_replay() {
+        let main = gradient_image(20, 15);
+        let searched = crop(&main, rect(3, 4, 5, 3));
+        let templates = [template("t", &searched)];
+        let config = SearchConfig::builder()
+            .max_mse(0.5)
+            .max_results(3)
+            .build()
+            .unwrap();
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let events_cloned = events.clone();
+        let results = block_on(main.find_subimage(
+            &templates,
+            &config,
+            |_| {},
+            move |template_index, event| events_cloned.borrow_mut().push((template_index, event)),
+            None,
+            &CancellationToken::default(),
+        ))
+        .unwrap()
+        .results;
+        let mut replica = main.new_search_results(&templates[0], &config);
+        for (template_index, event) in events.take() {
+            assert_eq!(template_index, 0);
+            replica.apply(event);
+        }
+        assert_eq!(replica.get_matches(), results[0].get_matches());
+        assert_eq!(replica.has_overflown(), results[0].has_overflown());
+    }
+
+    #[test]
+    fn test_candidate_ranges_without_regions() {
+        let ranges = candidate_ranges(&[], 5, 10);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0], 0..10);
+    }
+
+    #[test]
+    fn test_candidate_ranges_row_outside() {
+        let regions = [rect(2, 3, 4, 2)];
+        assert_eq!(candidate_ranges(&regions, 2, 10), vec![]);
+        assert_eq!(candidate_ranges(&regions, 3, 10), vec![2..6]);
+        assert_eq!(candidate_ranges(&regions, 4, 10), vec![2..6]);
+        assert_eq!(candidate_ranges(&regions, 5, 10), vec![]);
+    }
+
+    #[test]
+    fn test_candidate_ranges_merge_and_clip() {
+        let regions = [rect(6, 0, 10, 1), rect(1, 0, 2, 1), rect(2, 0, 5, 1)];
+        assert_eq!(candidate_ranges(&regions, 0, 8), vec![1..8]);
+        let regions = [rect(1, 0, 2, 1), rect(5, 0, 2, 1), rect(20, 0, 2, 1)];
+        assert_eq!(candidate_ranges(&regions, 0, 8), vec![1..3, 5..7]);
+    }
 }
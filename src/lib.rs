@@ -1,46 +1,229 @@
-use gloo::utils::{document, window};
+use gloo::events::EventListener;
+use gloo::storage::{LocalStorage, Storage};
 use log::Level;
-use wasm_bindgen::JsCast;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
 use web_sys::FileList;
-use web_sys::FileReader;
 use yew::prelude::*;
-mod image;
-use image::{ImageData, SearchResults};
+pub mod batch;
+pub mod config;
+pub mod error;
+pub mod image;
+pub mod presets;
+use batch::{BatchEntry, rank};
+use config::SearchConfig;
+use error::Error;
+use image::{
+    CancellationToken, DecodedImage, DecodedTemplate, ImageData, ImageMetadata, Mask, SearchEvent,
+    SearchOutcome, SearchResults, now_ms,
+};
+use presets::Preset;
 
 mod components {
+    pub mod batch_results;
+    pub mod diagnostics;
     pub mod image_input;
+    pub mod match_inspector;
+    pub mod presets;
+    pub mod region_editor;
+    pub mod result_viewer;
     pub mod search_params;
     pub mod search_results;
     pub mod search_summary;
 }
 
+use components::batch_results::BatchResultsView;
+use components::diagnostics::Diagnostics;
+use components::match_inspector::{Inspection, MatchInspector};
+use components::result_viewer::MatchId;
 use components::search_params::SearchParams;
-use components::search_results::SearchError;
+use components::search_results::{SearchError, SearchResultsView};
 use components::search_summary::SearchSummary;
 
+// Image uploaded by the user. The bytes are decoded in Rust, the URL is used only for displaying it.
+#[derive(Clone)]
+pub struct UploadedImage {
+    pub name: String,
+    pub url: String,        // Object URL of the uploaded file
+    pub bytes: Rc<Vec<u8>>, // Kept for decoding again when the image metadata setting changes
+    // Decoded once when uploaded and shared by all searches, an error is reported by the search
+    pub frames: Rc<Result<Vec<DecodedImage>, Error>>,
+}
+
+impl PartialEq for UploadedImage {
+    fn eq(&self, other: &Self) -> bool {
+        // Object URLs are unique, so the bytes need not be compared
+        self.url == other.url
+    }
+}
+
+impl UploadedImage {
+    fn new(name: String, url: String, bytes: Vec<u8>, apply_metadata: bool) -> UploadedImage {
+        let frames = DecodedImage::decode_frames(&name, &bytes, apply_metadata)
+            .inspect_err(|err| log::warn!("{}", err));
+        UploadedImage {
+            name,
+            url,
+            bytes: Rc::new(bytes),
+            frames: Rc::new(frames),
+        }
+    }
+
+    // The same file and URL, decoded with another setting
+    fn decode_again(&self, apply_metadata: bool) -> UploadedImage {
+        let frames = DecodedImage::decode_frames(&self.name, &self.bytes, apply_metadata);
+        UploadedImage {
+            frames: Rc::new(frames),
+            ..self.clone()
+        }
+    }
+
+    fn frames(&self) -> Result<&[DecodedImage], Error> {
+        self.frames.as_ref().as_deref().map_err(Error::clone)
+    }
+
+    // Templates and masks are still images, so only the first frame of an animation is used
+    fn first_frame(&self) -> Result<&DecodedImage, Error> {
+        self.frames()?.first().ok_or_else(|| Error::Decode {
+            image: self.name.clone(),
+            reason: "the image has no frames".to_string(),
+        })
+    }
+
+    /// Metadata detected in the file, None when it cannot be decoded
+    pub fn metadata(&self) -> Option<&ImageMetadata> {
+        self.first_frame().ok().map(DecodedImage::metadata)
+    }
+}
+
+/// Slot receiving images pasted from the clipboard.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PasteTarget {
+    #[default]
+    MainImages, // A screenshot is usually the image to search in
+    SearchImages,
+}
+
+// A frame of a main image, the frames of an animation are searched one after another
+#[derive(Clone, Copy)]
+struct SearchedFrame {
+    input_index: usize, // Index of the main image
+    index: usize,
+    count: usize,
+}
+
+// Object URLs keep the files in memory until they are revoked
+fn revoke_urls<'a>(images: impl IntoIterator<Item = &'a UploadedImage>) {
+    for image in images {
+        if let Err(err) = web_sys::Url::revoke_object_url(&image.url) {
+            log::warn!("Error revoking URL of {}: {:?}", image.name, err);
+        }
+    }
+}
+
+fn revoke_object_urls(urls: &[String]) {
+    for url in urls {
+        if let Err(err) = web_sys::Url::revoke_object_url(url) {
+            log::warn!("Error revoking URL {}: {:?}", url, err);
+        }
+    }
+}
+
+// Images decoded in Rust, e.g., single frames of animations, which the browser would display animated
+fn create_png_urls<'a>(
+    images: impl IntoIterator<Item = &'a DecodedImage>,
+) -> Result<Vec<String>, Error> {
+    let browser_error = |e| Error::Browser(format!("error displaying images: {:?}", e));
+    let mut urls = Vec::new();
+    for image in images {
+        let png = image.to_png()?;
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(png.as_slice()));
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type("image/png");
+        let url = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+            .and_then(|blob| web_sys::Url::create_object_url_with_blob(&blob));
+        match url {
+            Ok(url) => urls.push(url),
+            Err(err) => {
+                revoke_object_urls(&urls);
+                return Err(browser_error(err));
+            }
+        }
+    }
+    Ok(urls)
+}
+
+/// Saves the bytes as a file through the download of the browser.
+pub(crate) fn download_file(file_name: &str, bytes: &[u8], mime_type: &str) -> Result<(), Error> {
+    let browser_error = |e| Error::Browser(format!("error downloading {}: {:?}", file_name, e));
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime_type);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+        .map_err(browser_error)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(browser_error)?;
+    let anchor: web_sys::HtmlAnchorElement = gloo::utils::document()
+        .create_element("a")
+        .map_err(browser_error)?
+        .dyn_into()
+        .map_err(|_| Error::Browser("anchor element is not an anchor".to_string()))?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url).map_err(browser_error)
+}
+
 // Main application state
 #[derive(Default)]
 struct SubimageSearch {
-    main_image: Option<String>,
-    search_image: Option<String>,
-    processing: bool, // Track if processing is in progress
-    result: Option<Result<SearchResults, String>>, // Store result message
-    progress: f32,    // Track progress of image processing (0.0 to 1.0)
-    max_mse: f64,     // Maximum mean squared error threshold
-    max_results: u16, // Maximum number of search results
+    main_images: Vec<UploadedImage>,   // More main images mean batch mode
+    search_images: Vec<UploadedImage>, // Templates searched in a single pass
+    mask_image: Option<UploadedImage>, // Optional image marking excluded areas of the main image
+    processing: bool,                  // Track if processing is in progress
+    result: Option<Result<Rc<Vec<BatchEntry>>, Error>>, // Ranked results, one entry per main image
+    cancellation: CancellationToken,   // Stops the running search
+    selected_entry: Option<usize>,     // Batch entry whose details are displayed
+    selected_frame: usize,             // Frame of the selected entry whose matches are displayed
+    live_results: Option<(SearchedFrame, Vec<SearchResults>)>, // Matches of the frame being searched
+    frame_urls: HashMap<usize, Vec<String>>, // Object URLs of the frames of animated main images, by input index
+    progress: f32,                           // Track progress of image processing (0.0 to 1.0)
+    config: SearchConfig,                    // Settings shared by all the templates and main images
+    searched_config: SearchConfig,           // Settings of the displayed results
+    pins: Vec<(String, f64)>,                // Template thresholds for the next searches, by name
+    searched_pins: Vec<(String, f64)>,       // Template thresholds of the displayed results
+    user_presets: Vec<Preset>,               // Saved in the local storage of the browser
+    paste_target: PasteTarget,
+    inspection: Option<Result<Inspection, Error>>, // Details of a match selected in the list
+    _paste_listener: Option<EventListener>,        // Removed when dropped
 }
 
+const USER_PRESETS_KEY: &str = "subimage-search.presets";
+
 // Application messages
 enum Msg {
-    MainImageLoaded(String),
-    SearchImageLoaded(String),
+    MainImagesLoaded(Vec<UploadedImage>),
+    SearchImagesLoaded(Vec<UploadedImage>),
+    MaskImageLoaded(UploadedImage),
+    RemoveMaskImage,
     ProcessImages,
     UpdateProgress(f32),
-    ProcessingComplete(Option<Result<SearchResults, String>>), // Result message from processing
-    UpdateMaxMse(f64),
-    UpdateMaxResults(u16), // Message to update max_results
+    FramesDecoded(usize, Vec<String>), // Main image index and URLs of its frames
+    LiveSearchStarted(SearchedFrame, Vec<SearchResults>), // Empty results for each template of the frame
+    LiveSearchEvent(usize, SearchEvent),                  // Template index and the change
+    CancelSearch,
+    ProcessingComplete(Result<Vec<BatchEntry>, Error>), // Result message from processing
+    SelectBatchEntry(usize),
+    SelectFrame(usize),
+    InspectMatch(MatchId),
+    CloseInspection,
+    UpdateConfig(SearchConfig),
+    PinThreshold(String, f64), // Template name and the maximum difference picked for it
+    UpdateUserPresets(Vec<Preset>),
+    SetPasteTarget(PasteTarget),
+    PasteImages(FileList),
     NewSearch,
 }
 
@@ -48,62 +231,103 @@ impl Component for SubimageSearch {
     type Message = Msg;
     type Properties = ();
 
-    fn create(_ctx: &Context<Self>) -> Self {
-        console_log::init_with_level(Level::Debug).expect("error initializing log");
+    fn create(ctx: &Context<Self>) -> Self {
+        if let Err(err) = console_log::init_with_level(Level::Debug) {
+            // The application works without logging, so there is no reason to fail
+            web_sys::console::warn_1(&format!("error initializing log: {}", err).into());
+        }
         log::info!("Subimage Search Application Initialized with Yew");
+        // Missing or unreadable presets must not prevent using the application
+        let user_presets = LocalStorage::get(USER_PRESETS_KEY).unwrap_or_else(|err| {
+            log::info!("No saved presets loaded: {}", err);
+            Vec::new()
+        });
+        let link = ctx.link().clone();
+        let paste_listener = EventListener::new(&gloo::utils::document(), "paste", move |event| {
+            let Some(files) = event
+                .dyn_ref::<web_sys::ClipboardEvent>()
+                .and_then(|e| e.clipboard_data())
+                .and_then(|data| data.files())
+                .filter(|files| files.length() > 0)
+            else {
+                // Pasting text, e.g., into the configuration editor, keeps working
+                return;
+            };
+            event.prevent_default();
+            link.send_message(Msg::PasteImages(files));
+        });
         Self {
-            max_mse: 0.01,
-            max_results: 10,
+            user_presets,
+            _paste_listener: Some(paste_listener),
             ..Self::default()
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::MainImageLoaded(data_url) => {
-                self.main_image = Some(data_url);
+            Msg::MainImagesLoaded(images) => {
+                revoke_urls(&self.main_images);
+                self.revoke_frame_urls();
+                self.main_images = images;
+                // Regions of the previous image are meaningless for the new one
+                self.config.regions.clear();
+                self.config.excluded_regions.clear();
+                self.config.template_region = None;
+                true
+            }
+            Msg::SearchImagesLoaded(images) => {
+                revoke_urls(&self.search_images);
+                self.search_images = images;
+                // Thresholds picked for the previous templates don't apply to new ones of the same name
+                self.pins.clear();
+                true
+            }
+            Msg::MaskImageLoaded(image) => {
+                revoke_urls(&self.mask_image);
+                self.mask_image = Some(image);
                 true
             }
-            Msg::SearchImageLoaded(data_url) => {
-                self.search_image = Some(data_url);
+            Msg::RemoveMaskImage => {
+                revoke_urls(&self.mask_image);
+                self.mask_image = None;
                 true
             }
             Msg::ProcessImages => {
                 log::info!("Starting image processing...");
                 self.processing = true;
                 self.result = None;
+                self.live_results = None;
+                self.revoke_frame_urls();
+                self.close_inspection();
                 self.progress = 0.0; // Reset progress
+                self.cancellation = CancellationToken::default();
 
                 // Launch async image processing
                 let link = ctx.link().clone();
-                let max_mse = self.max_mse;
-                let max_results = self.max_results;
+                let cancellation = self.cancellation.clone();
+                self.searched_config = self.config.clone();
+                self.searched_pins = self.pins.clone();
+                let config = self.config.clone();
+                let pins = self.pins.clone();
+                // Cloning is cheap, the bytes are shared
+                let main_images = self.main_images.clone();
+                let search_images = self.search_images.clone();
+                let mask_image = self.mask_image.clone();
                 spawn_local(async move {
-                    match load_images_for_processing().await {
-                        Ok((main_img_data, search_img_data)) => {
-                            log::info!("Images loaded successfully");
-                            // Images loaded successfully - now you can process them
-                            let link_cloned = link.clone();
-                            let result = main_img_data
-                                .find_subimage(
-                                    &search_img_data,
-                                    move |progress| {
-                                        link_cloned.send_message(Msg::UpdateProgress(progress))
-                                    },
-                                    max_mse,
-                                    max_results,
-                                )
-                                .await;
-                            link.send_message(Msg::ProcessingComplete(Some(result)));
-                        }
-                        Err(err) => {
-                            log::error!("Error loading images: {}", err);
-                            window()
-                                .alert_with_message(&format!("Error loading images: {}", err))
-                                .unwrap();
-                            link.send_message(Msg::ProcessingComplete(None));
-                        }
+                    let result = search_batch(
+                        &main_images,
+                        &search_images,
+                        mask_image.as_ref(),
+                        &config,
+                        &pins,
+                        &cancellation,
+                        &link,
+                    )
+                    .await;
+                    if let Err(err) = &result {
+                        log::error!("Error searching: {}", err);
                     }
+                    link.send_message(Msg::ProcessingComplete(result));
                 });
 
                 true
@@ -112,22 +336,132 @@ impl Component for SubimageSearch {
                 self.progress = progress;
                 true
             }
+            Msg::FramesDecoded(input_index, urls) => {
+                if let Some(previous) = self.frame_urls.insert(input_index, urls) {
+                    revoke_object_urls(&previous);
+                }
+                true
+            }
+            Msg::LiveSearchStarted(frame, empty_results) => {
+                self.live_results = Some((frame, empty_results));
+                true
+            }
+            Msg::LiveSearchEvent(template_index, event) => {
+                if let Some(results) = self
+                    .live_results
+                    .as_mut()
+                    .and_then(|(_, results)| results.get_mut(template_index))
+                {
+                    results.apply(event);
+                }
+                true
+            }
             Msg::ProcessingComplete(result) => {
                 self.processing = false;
-                self.result = result;
+                self.live_results = None;
+                // A single main image shows its details right away
+                self.selected_entry = result.as_ref().ok().filter(|r| r.len() == 1).map(|_| 0);
+                self.selected_frame = result
+                    .as_ref()
+                    .ok()
+                    .and_then(|entries| entries.first())
+                    .and_then(BatchEntry::get_best_frame)
+                    .unwrap_or(0);
+                self.result = Some(result.map(Rc::new));
                 self.progress = 1.0; // Ensure progress is complete
                 true
             }
-            Msg::UpdateMaxMse(new_max_mse_percent) => {
-                self.max_mse = new_max_mse_percent / 100.0;
+            Msg::CancelSearch => {
+                self.cancellation.cancel();
+                false
+            }
+            Msg::UpdateConfig(config) => {
+                if config.apply_image_metadata != self.config.apply_image_metadata {
+                    let apply_metadata = config.apply_image_metadata;
+                    for image in self
+                        .main_images
+                        .iter_mut()
+                        .chain(self.search_images.iter_mut())
+                        .chain(self.mask_image.iter_mut())
+                    {
+                        *image = image.decode_again(apply_metadata);
+                    }
+                }
+                self.config = config;
+                true
+            }
+            Msg::PinThreshold(name, max_mse) => {
+                // The other templates keep the threshold mode of the config
+                match self.pins.iter_mut().find(|(n, _)| *n == name) {
+                    Some((_, pinned)) => *pinned = max_mse,
+                    None => self.pins.push((name, max_mse)),
+                }
+                // The picked threshold is an MSE, not a deviation
+                self.config.threshold_unit = self.config.threshold_unit.mse_unit();
+                true
+            }
+            Msg::UpdateUserPresets(user_presets) => {
+                if let Err(err) = LocalStorage::set(USER_PRESETS_KEY, &user_presets) {
+                    log::error!("Error saving presets: {}", err);
+                }
+                self.user_presets = user_presets;
                 true
             }
-            Msg::UpdateMaxResults(new_max_results) => {
-                self.max_results = new_max_results;
+            Msg::SetPasteTarget(target) => {
+                self.paste_target = target;
+                true
+            }
+            Msg::PasteImages(files) => {
+                // The inputs are not displayed with the results or disabled during the search
+                if self.processing || self.result.is_some() {
+                    log::info!("Ignoring pasted images, no image input is active");
+                    return false;
+                }
+                let on_upload = match self.paste_target {
+                    PasteTarget::MainImages => {
+                        self.handle_file_upload(ctx, Msg::MainImagesLoaded, true)
+                    }
+                    PasteTarget::SearchImages => {
+                        self.handle_file_upload(ctx, Msg::SearchImagesLoaded, true)
+                    }
+                };
+                on_upload.emit(files);
+                false
+            }
+            Msg::SelectBatchEntry(index) => {
+                self.selected_entry = Some(index);
+                self.close_inspection();
+                // Animations open at the frame with the best match
+                self.selected_frame = match &self.result {
+                    Some(Ok(entries)) => entries
+                        .get(index)
+                        .and_then(BatchEntry::get_best_frame)
+                        .unwrap_or(0),
+                    _ => 0,
+                };
+                true
+            }
+            Msg::SelectFrame(index) => {
+                self.selected_frame = index;
+                self.close_inspection();
+                true
+            }
+            Msg::InspectMatch(id) => {
+                self.close_inspection();
+                let inspection = self.inspect_match(id);
+                if let Err(err) = &inspection {
+                    log::error!("Error inspecting the match: {}", err);
+                }
+                self.inspection = Some(inspection);
+                true
+            }
+            Msg::CloseInspection => {
+                self.close_inspection();
                 true
             }
             Msg::NewSearch => {
                 self.result = None;
+                self.close_inspection();
                 true
             }
         }
@@ -135,7 +469,9 @@ impl Component for SubimageSearch {
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         // Determine if the Process button should be enabled
-        let both_images_loaded = self.main_image.is_some() && self.search_image.is_some();
+        // A template can also be cropped from the main image
+        let both_images_loaded = !self.main_images.is_empty()
+            && (!self.search_images.is_empty() || self.config.template_region.is_some());
         let process_button_class = if both_images_loaded && !self.processing {
             "process-button ready"
         } else {
@@ -147,9 +483,11 @@ impl Component for SubimageSearch {
 
         // Format progress percentage
         let progress_percent = (self.progress * 100.0) as u32;
+        let batch_size = self.main_images.len();
+        let batch_position = ((self.progress * batch_size as f32) as usize + 1).min(batch_size);
 
         html! {
-            <div class="container">
+            <div class={classes!("container", (!self.config.apply_image_metadata).then_some("raw-pixels"))}>
                 <h1>{"Subimage Search"}</h1>
                 {
                     if self.result.is_none() {
@@ -157,15 +495,20 @@ impl Component for SubimageSearch {
                             <>
 
                                 <SearchParams
-                                    max_mse={self.max_mse}
-                                    max_results={self.max_results}
-                                    main_image={self.main_image.clone()}
-                                    search_image={self.search_image.clone()}
+                                    config={self.config.clone()}
+                                    user_presets={self.user_presets.clone()}
+                                    main_images={self.main_images.clone()}
+                                    mask_image={self.mask_image.clone()}
+                                    search_images={self.search_images.clone()}
                                     disabled={self.processing}
-                                    on_max_mse_change={ctx.link().callback(Msg::UpdateMaxMse)}
-                                    on_max_results_change={ctx.link().callback(Msg::UpdateMaxResults)}
-                                    on_main_image_upload={self.handle_file_upload(ctx, Msg::MainImageLoaded)}
-                                    on_search_image_upload={self.handle_file_upload(ctx, Msg::SearchImageLoaded)}
+                                    on_config_change={ctx.link().callback(Msg::UpdateConfig)}
+                                    on_user_presets_change={ctx.link().callback(Msg::UpdateUserPresets)}
+                                    on_mask_image_upload={self.handle_file_upload(ctx, |mut images| Msg::MaskImageLoaded(images.remove(0)), false)}
+                                    on_mask_image_remove={ctx.link().callback(|_| Msg::RemoveMaskImage)}
+                                    on_main_image_upload={self.handle_file_upload(ctx, Msg::MainImagesLoaded, true)}
+                                    on_search_image_upload={self.handle_file_upload(ctx, Msg::SearchImagesLoaded, true)}
+                                    paste_target={self.paste_target}
+                                    on_paste_target_change={ctx.link().callback(Msg::SetPasteTarget)}
                                 />
 
                                 <div class="action-section">
@@ -183,12 +526,36 @@ impl Component for SubimageSearch {
                                         }
                                     </button>
 
+                                    {
+                                        if self.processing {
+                                            html! {
+                                                <button
+                                                    class="cancel-button"
+                                                    onclick={ctx.link().callback(|_| Msg::CancelSearch)}
+                                                >
+                                                    {"Cancel"}
+                                                </button>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
+                                    }
+
                                     {
                                         if self.processing {
                                             html! {
                                                 <div class="progress-container">
                                                     <progress value={self.progress.to_string()} max="1"></progress>
                                                     <span class="progress-text">{format!("{}%", progress_percent)}</span>
+                                                    {
+                                                        if batch_size > 1 {
+                                                            html! {
+                                                                <span class="progress-text">{format!("Image {} of {}", batch_position, batch_size)}</span>
+                                                            }
+                                                        } else {
+                                                            html! {}
+                                                        }
+                                                    }
                                                     <div class="progress-hint">
                                                         {"Progress indicator might be sometimes inconsistent due to various optimizations that apply on some part of the image more than on others."}
                                                     </div>
@@ -205,9 +572,14 @@ impl Component for SubimageSearch {
                     } else {
                         html! {
                             <SearchSummary
-                                search_image={self.search_image.clone().unwrap_or_default()}
-                                max_mse={self.max_mse}
-                                max_results={self.max_results}
+                                search_images={self.search_images.clone()}
+                                config={self.searched_config.clone()}
+                                next_config={self.config.clone()}
+                                pinned_thresholds={self.searched_pins.clone()}
+                                next_pinned_thresholds={self.pins.clone()}
+                                has_mask_image={self.mask_image.is_some()}
+                                auto_thresholds={self.auto_thresholds()}
+                                on_pin_threshold={ctx.link().callback(|(name, max_mse)| Msg::PinThreshold(name, max_mse))}
                                 on_new_search={ctx.link().callback(|_| Msg::NewSearch)}
                             />
                         }
@@ -216,71 +588,47 @@ impl Component for SubimageSearch {
 
                 <div id="results">
                     {
-                        if let Some(result) = &self.result {
-                            match result {
-                                Ok(search_results) => {
-                                    html! {
-                                        <div class="result-container">
-                                            <h2>{"Search results"}</h2>
-                                            <div class="result-message">
-                                                <h3>{if search_results.has_overflown() {
-                                                    format!("Found many matches, showing {} most relevant", search_results.get_matches().len())
-                                                } else if search_results.get_matches().is_empty() {
-                                                    "No matches found".to_string()
-                                                } else {
-                                                    format!("Found {} matches", search_results.get_matches().len())
-                                                }}</h3>
-                                            </div>
-                                            <div class="main-image-container">
-                                                <img
-                                                    src={self.main_image.clone().unwrap_or_default()}
-                                                    alt="Main image with matches"
-                                                    class="result-main-image"
+                        if let Some((frame, live_results)) = &self.live_results {
+                            html! {
+                                <SearchResultsView
+                                    main_image={self.frame_url(frame.input_index, frame.index)}
+                                    results={Rc::new(live_results.clone())}
+                                    frame_index={frame.index}
+                                    frame_count={frame.count}
+                                    live={true}
+                                    unit={self.searched_config.threshold_unit}
+                                    metric={self.searched_config.metric}
+                                />
+                            }
+                        } else if let Some(Err(error)) = &self.result {
+                            html! {
+                                <SearchError error={error.clone()} />
+                            }
+                        } else if let Some(Ok(entries)) = &self.result {
+                            html! {
+                                <>
+                                    {
+                                        if entries.len() > 1 {
+                                            html! {
+                                                <BatchResultsView
+                                                    entries={entries.clone()}
+                                                    selected={self.selected_entry}
+                                                    on_select={ctx.link().callback(Msg::SelectBatchEntry)}
+                                                    unit={self.searched_config.threshold_unit}
+                                                    metric={self.searched_config.metric}
                                                 />
-                                                {
-                                                    search_results.get_matches().iter().enumerate().map(|(i, m)| {
-                                                        let x_percent = m.x as f64 / search_results.get_main_width() as f64 * 100.0;
-                                                        let y_percent = m.y as f64 / search_results.get_main_height() as f64 * 100.0;
-                                                        let width_percent = search_results.get_template_width() as f64 / search_results.get_main_width() as f64 * 100.0;
-                                                        let height_percent = search_results.get_template_height() as f64 / search_results.get_main_height() as f64 * 100.0;
-
-                                                        html! {
-                                                            <div
-                                                                class="match-overlay"
-                                                                style={format!(
-                                                                    "left: {}%; top: {}%; width: {}%; height: {}%",
-                                                                    x_percent, y_percent, width_percent, height_percent
-                                                                )}
-                                                                title={format!("#{} | MSE: {:.4}", i+1, m.get_mse(search_results.get_squared_errors_divisor()))}
-                                                                data-match-id={i.to_string()}
-                                                            />
-                                                        }
-                                                    }).collect::<Html>()
-                                                }
-                                            </div>
-                                            <ol class="matches-list">
-                                                {
-                                                    search_results.get_matches().iter().enumerate().map(|(i, m)| {
-                                                        html! {
-                                                            <li class="match-item" data-match-id={i.to_string()}>
-                                                                {format!("Match at ({}, {}) - MSE: {:.4}%",
-                                                                    m.x,
-                                                                    m.y,
-                                                                    m.get_mse(search_results.get_squared_errors_divisor())*100.0
-                                                                )}
-                                                            </li>
-                                                        }
-                                                    }).collect::<Html>()
-                                                }
-                                            </ol>
-                                        </div>
+                                            }
+                                        } else {
+                                            html! {}
+                                        }
                                     }
-                                }
-                                Err(error_message) => {
-                                    html! {
-                                        <SearchError message={error_message.clone()} />
+                                    {
+                                        match self.selected_entry.and_then(|i| entries.get(i)) {
+                                            Some(entry) => self.view_batch_entry(ctx, entry),
+                                            None => html! {},
+                                        }
                                     }
-                                }
+                                </>
                             }
                         } else {
                             html! {}
@@ -302,54 +650,401 @@ impl Component for SubimageSearch {
 
 // Helper methods for SubimageSearch
 impl SubimageSearch {
+    // Still images are displayed from the uploaded file, frames of animations from their own PNGs
+    fn frame_url(&self, input_index: usize, frame_index: usize) -> String {
+        self.frame_urls
+            .get(&input_index)
+            .and_then(|urls| urls.get(frame_index))
+            .or_else(|| self.main_images.get(input_index).map(|i| &i.url))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn revoke_frame_urls(&mut self) {
+        for urls in self.frame_urls.values() {
+            revoke_object_urls(urls);
+        }
+        self.frame_urls.clear();
+    }
+
+    fn close_inspection(&mut self) {
+        if let Some(Ok(inspection)) = self.inspection.take() {
+            revoke_object_urls(&inspection.urls());
+        }
+    }
+
+    // Compares the match of the displayed frame with its template pixel by pixel
+    fn inspect_match(&self, (template_index, match_index): MatchId) -> Result<Inspection, Error> {
+        let not_found = || Error::invalid_parameter("match", "the match is no longer displayed");
+        let Some(Ok(entries)) = &self.result else {
+            return Err(not_found());
+        };
+        let entry = self
+            .selected_entry
+            .and_then(|index| entries.get(index))
+            .ok_or_else(not_found)?;
+        let outcome = entry
+            .outcome
+            .as_ref()
+            .map_err(Error::clone)?
+            .get(self.selected_frame)
+            .ok_or_else(not_found)?;
+        let results = outcome.results.get(template_index).ok_or_else(not_found)?;
+        let result = results
+            .get_matches()
+            .get(match_index)
+            .ok_or_else(not_found)?;
+        let frame = self
+            .main_images
+            .get(entry.input_index)
+            .ok_or_else(not_found)?
+            .frames()?
+            .get(self.selected_frame)
+            .ok_or_else(not_found)?;
+        // The images are the same as in the search, as they cannot be changed while its results are displayed
+        let named_templates = named_templates(
+            &self.main_images,
+            &self.search_images,
+            &self.searched_config,
+        )?;
+        let templates = decoded_templates(&named_templates, &self.searched_pins);
+        let template = templates.get(template_index).ok_or_else(not_found)?;
+        let error_map = frame.error_map(
+            template,
+            result.x,
+            result.y,
+            self.searched_config.channels,
+            frame.search_depth(&templates),
+        )?;
+        let region = frame.crop(&image::Rect {
+            x: result.x,
+            y: result.y,
+            width: template.image.width(),
+            height: template.image.height(),
+        })?;
+        let urls = create_png_urls([template.image, &region, &error_map.to_heatmap()])?;
+        Ok(Inspection {
+            template_name: template.name.clone(),
+            x: result.x,
+            y: result.y,
+            // The MSE of the pixels, also when the match was scored by another metric
+            mse: error_map.get_mse(),
+            max_error: error_map.get_max() as f64,
+            template_url: urls[0].clone(),
+            region_url: urls[1].clone(),
+            heatmap_url: urls[2].clone(),
+        })
+    }
+
+    // The loosest picked threshold of each template over all the main images and frames, so pinning it finds every match
+    fn auto_thresholds(&self) -> Vec<(String, f64)> {
+        let mut thresholds: Vec<(String, f64)> = Vec::new();
+        let entries = match &self.result {
+            Some(Ok(entries)) => entries.as_slice(),
+            _ => &[],
+        };
+        let picked = entries
+            .iter()
+            .filter_map(|entry| entry.outcome.as_ref().ok())
+            .flatten()
+            .flat_map(|frame| &frame.results)
+            .filter_map(|r| Some((r.get_template_name(), r.get_auto_threshold()?)));
+        for (name, threshold) in picked {
+            match thresholds.iter_mut().find(|(n, _)| n == name) {
+                Some((_, max)) => *max = max.max(threshold),
+                None => thresholds.push((name.to_string(), threshold)),
+            }
+        }
+        thresholds
+    }
+
+    fn view_batch_entry(&self, ctx: &Context<Self>, entry: &BatchEntry) -> Html {
+        let frame_index = self
+            .selected_frame
+            .min(entry.get_frame_count().saturating_sub(1));
+        match entry.outcome.as_ref().map(|frames| frames.get(frame_index)) {
+            Ok(None) => html! {},
+            Ok(Some(outcome)) => {
+                let frames_with_matches: Vec<usize> = entry
+                    .outcome
+                    .iter()
+                    .flatten()
+                    .enumerate()
+                    .filter(|(_, frame)| frame.results.iter().any(|r| !r.get_matches().is_empty()))
+                    .map(|(i, _)| i)
+                    .collect();
+                html! {
+                    <>
+                        <SearchResultsView
+                            main_image={self.frame_url(entry.input_index, frame_index)}
+                            results={Rc::new(outcome.results.clone())}
+                            frame_index={frame_index}
+                            frame_count={entry.get_frame_count()}
+                            frames_with_matches={frames_with_matches}
+                            on_frame_change={ctx.link().callback(Msg::SelectFrame)}
+                            on_inspect={ctx.link().callback(Msg::InspectMatch)}
+                            error_landscapes={outcome.error_landscapes.clone()}
+                            unit={self.searched_config.threshold_unit}
+                            metric={self.searched_config.metric}
+                        />
+                        {
+                            match &self.inspection {
+                                Some(Ok(inspection)) => html! {
+                                    <MatchInspector
+                                        inspection={inspection.clone()}
+                                        on_close={ctx.link().callback(|_| Msg::CloseInspection)}
+                                    />
+                                },
+                                Some(Err(error)) => html! {
+                                    <SearchError error={error.clone()} />
+                                },
+                                None => html! {},
+                            }
+                        }
+                        <Diagnostics
+                            statistics={outcome.statistics.clone()}
+                            template_names={outcome.results.iter().map(|r| r.get_template_name().to_string()).collect::<Vec<_>>()}
+                        />
+                    </>
+                }
+            }
+            Err(error) => {
+                html! {
+                    <SearchError error={error.clone()} />
+                }
+            }
+        }
+    }
+
     fn handle_file_upload(
         &self,
         ctx: &Context<Self>,
-        msg_creator: fn(String) -> Msg,
+        msg_creator: fn(Vec<UploadedImage>) -> Msg,
+        multiple: bool,
     ) -> Callback<FileList> {
         let link = ctx.link().clone();
+        let apply_metadata = self.config.apply_image_metadata;
 
         Callback::from(move |file_list: FileList| {
-            if let Some(file) = file_list.get(0) {
-                let file_reader = FileReader::new().unwrap();
-                let fr_clone = file_reader.clone();
-                let link_clone = link.clone();
-
-                let onload_closure = Closure::wrap(Box::new(move |_: Event| {
-                    let result = fr_clone.result().unwrap();
-                    if let Some(data_url) = result.as_string() {
-                        link_clone.send_message(msg_creator(data_url));
+            let files: Vec<web_sys::File> = (0..file_list.length())
+                .filter_map(|i| file_list.get(i))
+                .take(if multiple { usize::MAX } else { 1 })
+                .collect();
+            let link = link.clone();
+            spawn_local(async move {
+                let mut images = Vec::with_capacity(files.len());
+                for file in files {
+                    match read_uploaded_image(&file, apply_metadata).await {
+                        Ok(image) => images.push(image),
+                        Err(err) => log::error!("Error reading {}: {}", file.name(), err),
                     }
-                }) as Box<dyn FnMut(_)>);
-
-                file_reader.set_onload(Some(onload_closure.as_ref().unchecked_ref()));
-                file_reader.read_as_data_url(&file).unwrap();
-                onload_closure.forget();
-            }
+                }
+                if !images.is_empty() {
+                    link.send_message(msg_creator(images));
+                }
+            });
         })
     }
 }
 
+async fn read_uploaded_image(
+    file: &web_sys::File,
+    apply_metadata: bool,
+) -> Result<UploadedImage, Error> {
+    let buffer = wasm_bindgen_futures::JsFuture::from(file.array_buffer())
+        .await
+        .map_err(|e| Error::Decode {
+            image: file.name(),
+            reason: format!("error reading file: {:?}", e),
+        })?;
+    let url = web_sys::Url::create_object_url_with_blob(file)
+        .map_err(|e| Error::Browser(format!("error creating URL of {}: {:?}", file.name(), e)))?;
+    let bytes = js_sys::Uint8Array::new(&buffer).to_vec();
+    Ok(UploadedImage::new(file.name(), url, bytes, apply_metadata))
+}
+
 // Image processing functions
-async fn load_images_for_processing() -> Result<(ImageData, ImageData), String> {
-    // Create a promise that resolves when both images are loaded
-    let main_image_data = load_image_data("mainImagePreview").await?; // main_img_url
-    log::info!("main image loaded");
-    let search_image_data = load_image_data("searchImagePreview").await?;
-    log::info!("search image loaded");
-
-    Ok((main_image_data, search_image_data))
+type NamedTemplate<'a> = (String, Cow<'a, DecodedImage>);
+
+// Uploaded templates followed by the one cropped from the main image, in the order of the search results
+fn named_templates<'a>(
+    main_images: &'a [UploadedImage],
+    search_images: &'a [UploadedImage],
+    config: &SearchConfig,
+) -> Result<Vec<NamedTemplate<'a>>, Error> {
+    let mut templates = search_images
+        .iter()
+        .map(|image| Ok((image.name.clone(), Cow::Borrowed(image.first_frame()?))))
+        .collect::<Result<Vec<_>, Error>>()?;
+    // Like the regions, the template region is drawn on the first main image
+    if let (Some(region), Some(main_image)) = (&config.template_region, main_images.first()) {
+        templates.push((
+            format!("{} {}", main_image.name, region),
+            Cow::Owned(main_image.first_frame()?.crop(region)?),
+        ));
+    }
+    Ok(templates)
 }
 
-// Load a single image and extract its pixel data
+// Templates with a pinned threshold use it instead of the one of the config
+fn decoded_templates<'a>(
+    named_templates: &'a [NamedTemplate],
+    pinned_thresholds: &[(String, f64)],
+) -> Vec<DecodedTemplate<'a>> {
+    named_templates
+        .iter()
+        .map(|(name, image)| DecodedTemplate {
+            name: name.clone(),
+            image,
+            max_mse: pinned_thresholds
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, max_mse)| *max_mse),
+            max_results: None,
+        })
+        .collect()
+}
 
-async fn load_image_data(image_id: &str) -> Result<ImageData, String> {
-    let image: web_sys::HtmlImageElement = document()
-        .get_element_by_id(image_id)
-        .unwrap()
-        .dyn_into()
-        .unwrap();
-    ImageData::from_image(&image)
+async fn search_batch(
+    main_images: &[UploadedImage],
+    search_images: &[UploadedImage],
+    mask_image: Option<&UploadedImage>,
+    config: &SearchConfig,
+    pinned_thresholds: &[(String, f64)],
+    cancellation: &CancellationToken,
+    link: &html::Scope<SubimageSearch>,
+) -> Result<Vec<BatchEntry>, Error> {
+    // The images were decoded when uploaded, so repeated searches start right away
+    let named_templates = named_templates(main_images, search_images, config)?;
+    let templates = decoded_templates(&named_templates, pinned_thresholds);
+    // Only dark and light pixels are distinguished in the mask, so 8 bits are enough
+    let mask_image_data: Option<ImageData> = mask_image
+        .map(|image| image.first_frame()?.to_image_data())
+        .transpose()?;
+
+    let count = main_images.len();
+    let mut entries = Vec::with_capacity(count);
+    for (input_index, main_image) in main_images.iter().enumerate() {
+        let link_cloned = link.clone();
+        let outcome = search_main_image(
+            input_index,
+            main_image,
+            &templates,
+            mask_image_data.as_ref(),
+            config,
+            cancellation,
+            link,
+            move |progress| {
+                // Progress of the batch as a whole
+                link_cloned.send_message(Msg::UpdateProgress(
+                    (input_index as f32 + progress) / count as f32,
+                ))
+            },
+        )
+        .await;
+        if outcome == Err(Error::Cancelled) {
+            return Err(Error::Cancelled);
+        }
+        if let Err(err) = &outcome {
+            log::error!("Error searching {}: {}", main_image.name, err);
+        }
+        entries.push(BatchEntry {
+            name: main_image.name.clone(),
+            input_index,
+            outcome,
+        });
+    }
+    rank(&mut entries);
+    Ok(entries)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn search_main_image<F>(
+    input_index: usize,
+    main_image: &UploadedImage,
+    templates: &[DecodedTemplate<'_>],
+    mask_image_data: Option<&ImageData>,
+    config: &SearchConfig,
+    cancellation: &CancellationToken,
+    link: &html::Scope<SubimageSearch>,
+    progress_callback: F,
+) -> Result<Vec<SearchOutcome>, Error>
+where
+    F: Fn(f32) + 'static,
+{
+    let load_start = now_ms();
+    let frames = main_image.frames()?;
+    if frames.len() > 1 {
+        // Only the displayed image is affected, the matches can be listed anyway
+        match create_png_urls(frames) {
+            Ok(urls) => link.send_message(Msg::FramesDecoded(input_index, urls)),
+            Err(err) => log::error!("{}", err),
+        }
+    }
+    // All frames have the size of the whole animation, so only the excluded template region differs
+    let first_frame = &frames[0];
+    let frame_mask = |frame_index: usize| -> Result<Option<Mask>, Error> {
+        let excluded_regions = config.excluded_regions_in(input_index, frame_index);
+        if mask_image_data.is_none() && excluded_regions.is_empty() {
+            return Ok(None);
+        }
+        Mask::from_parts(
+            first_frame.width(),
+            first_frame.height(),
+            mask_image_data,
+            &excluded_regions,
+        )
+        .map(Some)
+    };
+    let first_frame_mask = frame_mask(0)?;
+    let later_frames_mask = if frames.len() > 1 {
+        frame_mask(1)?
+    } else {
+        None
+    };
+    let load_duration_ms = now_ms() - load_start;
+    let progress_callback = Rc::new(progress_callback);
+    let mut outcomes = Vec::with_capacity(frames.len());
+    for (frame_index, frame) in frames.iter().enumerate() {
+        let searched_frame = SearchedFrame {
+            input_index,
+            index: frame_index,
+            count: frames.len(),
+        };
+        link.send_message(Msg::LiveSearchStarted(
+            searched_frame,
+            frame.new_search_results(templates, config),
+        ));
+        let link_cloned = link.clone();
+        let progress_callback = progress_callback.clone();
+        let outcome = frame
+            .find_subimage(
+                templates,
+                config,
+                move |progress| {
+                    progress_callback((frame_index as f32 + progress) / searched_frame.count as f32)
+                },
+                move |template_index, event| {
+                    link_cloned.send_message(Msg::LiveSearchEvent(template_index, event))
+                },
+                if frame_index == 0 {
+                    first_frame_mask.as_ref()
+                } else {
+                    later_frames_mask.as_ref()
+                },
+                cancellation,
+            )
+            .await?;
+        outcomes.push(outcome);
+    }
+    outcomes[0].statistics.phases.insert(
+        0,
+        image::PhaseTiming {
+            name: "load main image".to_string(),
+            duration_ms: load_duration_ms,
+        },
+    );
+    Ok(outcomes)
 }
 
 // Starting the Yew application
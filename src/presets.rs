@@ -0,0 +1,133 @@
+use crate::config::{Channels, SearchConfig};
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// Named search settings, so a team can share them instead of typing the numbers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub config: SearchConfig,
+}
+
+impl Preset {
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::invalid_parameter(
+                "preset name",
+                "the name cannot be empty",
+            ));
+        }
+        self.config.validate()
+    }
+
+    /// Validates a preset saved or imported by the user, which must not be shadowed by a built-in one
+    pub fn validate_user_preset(&self) -> Result<(), Error> {
+        self.validate()?;
+        if builtin_presets().iter().any(|p| p.name == self.name) {
+            return Err(Error::invalid_parameter(
+                "preset name",
+                format!("\"{}\" is the name of a built-in preset", self.name),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        // Plain data without maps with non-string keys, so serialization cannot fail
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Preset, Error> {
+        let preset: Preset = serde_json::from_str(json)
+            .map_err(|e| Error::invalid_parameter("preset", e.to_string()))?;
+        preset.validate()?;
+        Ok(preset)
+    }
+
+    /// Suggested name of an exported file
+    pub fn file_name(&self) -> String {
+        let slug: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        format!("{}.preset.json", slug)
+    }
+}
+
+/// Presets for the most common kinds of images. They don't restrict the search to any regions.
+pub fn builtin_presets() -> Vec<Preset> {
+    vec![
+        Preset {
+            name: "Exact UI screenshot".to_string(),
+            config: SearchConfig {
+                max_mse: 0.0,
+                ..SearchConfig::default()
+            },
+        },
+        Preset {
+            name: "Tolerant JPEG photo".to_string(),
+            config: SearchConfig {
+                // Compression artifacts are spread over the whole template
+                max_mse: 0.02,
+                channels: Channels::Rgb,
+                ..SearchConfig::default()
+            },
+        },
+        Preset {
+            name: "Pixel art".to_string(),
+            config: SearchConfig {
+                // Palette colors are exact, but the surroundings of sprites vary a bit
+                max_mse: 0.001,
+                max_results: 50,
+                ..SearchConfig::default()
+            },
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_presets_are_valid() {
+        for preset in builtin_presets() {
+            assert_eq!(preset.validate(), Ok(()), "{}", preset.name);
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let preset = Preset {
+            name: "Team settings".to_string(),
+            config: SearchConfig {
+                max_results: 3,
+                ..SearchConfig::default()
+            },
+        };
+        assert_eq!(Preset::from_json(&preset.to_json()), Ok(preset.clone()));
+        assert_eq!(preset.file_name(), "team-settings.preset.json");
+        assert!(Preset::from_json(r#"{"name": " ", "config": {}}"#).is_err());
+        assert!(Preset::from_json(r#"{"name": "x", "config": {"max_results": 0}}"#).is_err());
+        assert!(Preset::from_json(r#"{"config": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_user_preset_names() {
+        let mut preset = Preset {
+            name: "Team settings".to_string(),
+            config: SearchConfig::default(),
+        };
+        assert_eq!(preset.validate_user_preset(), Ok(()));
+        preset.name = builtin_presets()[0].name.clone();
+        assert!(preset.validate().is_ok());
+        assert!(preset.validate_user_preset().is_err());
+    }
+}